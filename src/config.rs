@@ -0,0 +1,391 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use crate::salvo::{Port, Salvo, SalvoRoute};
+
+/// Where the configuration is read from when no path is given on the command
+/// line.
+pub const DEFAULT_CONFIG_PATH: &'static str = "config/ndi-router.yaml";
+
+/// Router configuration, read from a YAML file at startup.
+///
+/// Every key is optional, anything left out falls back to the defaults below.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Addresses the Videohub control server listens on.
+    pub listen: Vec<String>,
+
+    /// Addresses the HTTP API listens on, none to turn it off.
+    pub http_listen: Vec<String>,
+
+    /// Addresses the SW-P-08 control server listens on, none to turn it off.
+    pub swp08_listen: Vec<String>,
+
+    /// Addresses the Ember+ provider listens on, none to turn it off.
+    pub ember_listen: Vec<String>,
+
+    /// Number of NDI outputs the router publishes.
+    pub outputs: usize,
+
+    /// NDI names for individual outputs, keyed by output index. Outputs
+    /// without an entry are named `NDI output <index>`.
+    pub output_names: BTreeMap<usize, String>,
+
+    /// NDI groups to find sources in, all groups if empty.
+    pub find_groups: Vec<String>,
+
+    /// Extra IPs to query for sources, for finding them across subnets.
+    pub extra_ips: Vec<String>,
+
+    /// NDI groups the outputs are published in, the default group if empty.
+    pub output_groups: Vec<String>,
+
+    /// log4rs configuration file.
+    pub logging: PathBuf,
+
+    /// Where the NDI source to input slot assignments are kept.
+    pub source_map: PathBuf,
+
+    /// Where labels, routing and locks are saved.
+    pub state_file: PathBuf,
+
+    /// Where routing snapshots are saved.
+    pub snapshots: PathBuf,
+
+    /// Videohub protocol version reported to clients, some older control
+    /// software only accepts versions it knows.
+    pub protocol_version: String,
+
+    /// Model name reported to clients. Some control software only offers the
+    /// features of models it recognises.
+    pub model_name: String,
+
+    /// Unique ID reported to clients, generated on the first run and kept in
+    /// the state file when not set.
+    pub unique_id: Option<String>,
+
+    /// Name shown for this router in control software, until a client
+    /// changes it.
+    pub friendly_name: String,
+
+    /// Turns take mode on at startup, so routes to `take_outputs` are held
+    /// until they are taken. Control software can change both while running.
+    pub take_mode: bool,
+
+    /// Outputs that hold new routes until they are taken.
+    pub take_outputs: Vec<usize>,
+
+    /// Seconds a route can wait to be taken before it is dropped, 0 to keep
+    /// it until it is taken or replaced.
+    pub take_timeout: u64,
+
+    /// Named sets of crosspoints that are switched together.
+    pub salvos: BTreeMap<String, Vec<SalvoRoute>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: vec!["127.0.0.1:9990".to_owned()],
+            http_listen: vec!["127.0.0.1:8080".to_owned()],
+            swp08_listen: Vec::new(),
+            ember_listen: Vec::new(),
+            outputs: 16,
+            output_names: BTreeMap::new(),
+            find_groups: Vec::new(),
+            extra_ips: Vec::new(),
+            output_groups: Vec::new(),
+            logging: PathBuf::from("config/log4rs.yaml"),
+            source_map: PathBuf::from("sources.json"),
+            state_file: PathBuf::from("state.json"),
+            snapshots: PathBuf::from("snapshots.json"),
+            protocol_version: "2.8".to_owned(),
+            model_name: "Blackmagic Smart Videohub".to_owned(),
+            unique_id: None,
+            friendly_name: "NDI Router".to_owned(),
+            take_mode: false,
+            take_outputs: Vec::new(),
+            take_timeout: 60,
+            salvos: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_yaml::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read config {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse config {}: {}", path.display(), e),
+            ConfigError::Invalid(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads and validates the configuration at `path`. When `required` is
+    /// false a missing file just gives the default configuration.
+    pub fn load<P: AsRef<Path>>(path: P, required: bool) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+
+        let config: Config = match fs::read(path) {
+            Ok(contents) => serde_yaml::from_slice(&contents)
+                .map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && !required => Config::default(),
+            Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+        };
+
+        config.validate().map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.listen.is_empty() {
+            return Err("at least one listen address is needed".to_owned());
+        }
+
+        for addr in self.listen.iter().chain(&self.http_listen).chain(&self.swp08_listen).chain(&self.ember_listen) {
+            addr.parse::<SocketAddr>()
+                .map_err(|e| format!("listen address '{}' is not valid: {}", addr, e))?;
+        }
+
+        if self.outputs == 0 {
+            return Err("outputs must be at least 1".to_owned());
+        }
+
+        for (output, name) in &self.output_names {
+            if *output >= self.outputs {
+                return Err(format!("output_names has output {} but there are only {} outputs", output, self.outputs));
+            }
+
+            if name.trim().is_empty() || name.contains('\0') {
+                return Err(format!("output_names has an invalid name for output {}", output));
+            }
+        }
+
+        for group in self.find_groups.iter().chain(&self.output_groups) {
+            if group.trim().is_empty() || group.contains(',') || group.contains('\0') {
+                return Err(format!("NDI group '{}' is not valid", group));
+            }
+        }
+
+        for ip in &self.extra_ips {
+            ip.parse::<IpAddr>()
+                .map_err(|e| format!("extra IP '{}' is not valid: {}", ip, e))?;
+        }
+
+        for output in &self.take_outputs {
+            if *output >= self.outputs {
+                return Err(format!("take_outputs has output {} but there are only {} outputs", output, self.outputs));
+            }
+        }
+
+        for (name, routes) in &self.salvos {
+            if name.trim().is_empty() || name.contains('\n') {
+                return Err(format!("salvo name '{}' is not valid", name));
+            }
+
+            for route in routes {
+                self.salvo_output(&route.output)
+                    .ok_or_else(|| format!("salvo '{}' has output {} which does not exist", name, route.output))?;
+
+                if route.input == Port::Name(String::new()) {
+                    return Err(format!("salvo '{}' has an empty input name", name));
+                }
+            }
+        }
+
+        let mut version = self.protocol_version.splitn(2, '.');
+        let numeric = |part: Option<&str>| part.map_or(false, |p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()));
+        if !numeric(version.next()) || !numeric(version.next()) {
+            return Err(format!("protocol_version '{}' is not a version like 2.8", self.protocol_version));
+        }
+
+        if self.model_name.trim().is_empty() || self.model_name.contains('\n') {
+            return Err("model_name is not valid".to_owned());
+        }
+
+        if self.friendly_name.trim().is_empty() || self.friendly_name.contains('\n') {
+            return Err("friendly_name is not valid".to_owned());
+        }
+
+        if let Some(unique_id) = &self.unique_id {
+            if unique_id.is_empty() || !unique_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("unique_id '{}' is not valid, it should be letters and digits", unique_id));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for output in 0..self.outputs {
+            let name = self.output_name(output);
+            if !names.insert(name.clone()) {
+                return Err(format!("output name '{}' is used more than once", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Groups or IPs in the comma separated form the NDI SDK takes, `None`
+    /// when the list is empty so the SDK default is used.
+    pub fn ndi_list(list: &[String]) -> Option<String> {
+        if list.is_empty() {
+            None
+        } else {
+            Some(list.join(","))
+        }
+    }
+
+    /// Finds the output a salvo refers to, by index or NDI output name.
+    fn salvo_output(&self, output: &Port) -> Option<usize> {
+        match output {
+            Port::Index(i) if *i < self.outputs => Some(*i),
+            Port::Index(_) => None,
+            Port::Name(name) => (0..self.outputs).find(|&i| self.output_name(i) == *name),
+        }
+    }
+
+    /// The configured salvos, with outputs resolved to indexes.
+    pub fn salvos(&self) -> Vec<Salvo> {
+        self.salvos.iter()
+            .map(|(name, routes)| Salvo {
+                name: name.to_owned(),
+                routes: routes.iter()
+                    .filter_map(|route| Some((self.salvo_output(&route.output)?, route.input.clone())))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The NDI name published for `output`.
+    pub fn output_name(&self, output: usize) -> String {
+        match self.output_names.get(&output) {
+            Some(name) => name.to_owned(),
+            None => format!("NDI output {}", output),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(yaml: &str) -> Result<(), String> {
+        serde_yaml::from_str::<Config>(yaml).unwrap().validate()
+    }
+
+    #[test]
+    fn a_minimal_file_is_valid() {
+        assert_eq!(validate("outputs: 2"), Ok(()));
+        assert_eq!(validate("{}"), Ok(()));
+    }
+
+    #[test]
+    fn listen_addresses_must_be_valid() {
+        assert_eq!(validate("listen: []"), Err("at least one listen address is needed".to_owned()));
+        assert_eq!(
+            validate("listen: ['localhost:9990']"),
+            Err("listen address 'localhost:9990' is not valid: invalid socket address syntax".to_owned()),
+        );
+        assert_eq!(
+            validate("http_listen: ['127.0.0.1']"),
+            Err("listen address '127.0.0.1' is not valid: invalid socket address syntax".to_owned()),
+        );
+        assert_eq!(
+            validate("swp08_listen: ['0.0.0.0:70000']"),
+            Err("listen address '0.0.0.0:70000' is not valid: invalid socket address syntax".to_owned()),
+        );
+        assert_eq!(
+            validate("ember_listen: ['']"),
+            Err("listen address '' is not valid: invalid socket address syntax".to_owned()),
+        );
+        assert_eq!(
+            validate("extra_ips: ['10.0.0']"),
+            Err("extra IP '10.0.0' is not valid: invalid IP address syntax".to_owned()),
+        );
+    }
+
+    #[test]
+    fn output_names_must_be_valid_and_unique() {
+        assert_eq!(validate("outputs: 0"), Err("outputs must be at least 1".to_owned()));
+        assert_eq!(
+            validate("outputs: 2\noutput_names: {2: PGM}"),
+            Err("output_names has output 2 but there are only 2 outputs".to_owned()),
+        );
+        assert_eq!(
+            validate("output_names: {1: ' '}"),
+            Err("output_names has an invalid name for output 1".to_owned()),
+        );
+        assert_eq!(
+            validate("output_names: {1: \"PGM\\0\"}"),
+            Err("output_names has an invalid name for output 1".to_owned()),
+        );
+        assert_eq!(
+            validate("output_names: {0: PGM, 1: PGM}"),
+            Err("output name 'PGM' is used more than once".to_owned()),
+        );
+        assert_eq!(
+            validate("output_names: {0: NDI output 1}"),
+            Err("output name 'NDI output 1' is used more than once".to_owned()),
+        );
+        assert_eq!(validate("output_groups: ['Studio,B']"), Err("NDI group 'Studio,B' is not valid".to_owned()));
+    }
+
+    #[test]
+    fn take_outputs_must_exist() {
+        assert_eq!(
+            validate("outputs: 4\ntake_outputs: [0, 4]"),
+            Err("take_outputs has output 4 but there are only 4 outputs".to_owned()),
+        );
+    }
+
+    #[test]
+    fn salvo_ports_must_exist() {
+        assert_eq!(
+            validate("outputs: 4\nsalvos: {Wide: [{output: 4, input: 0}]}"),
+            Err("salvo 'Wide' has output 4 which does not exist".to_owned()),
+        );
+        assert_eq!(
+            validate("salvos: {Wide: [{output: PGM, input: 0}]}"),
+            Err("salvo 'Wide' has output 'PGM' which does not exist".to_owned()),
+        );
+        assert_eq!(
+            validate("salvos: {Wide: [{output: 0, input: ''}]}"),
+            Err("salvo 'Wide' has an empty input name".to_owned()),
+        );
+        assert_eq!(validate("salvos: {' ': []}"), Err("salvo name ' ' is not valid".to_owned()));
+    }
+
+    #[test]
+    fn identity_must_be_valid() {
+        assert_eq!(
+            validate("protocol_version: '2'"),
+            Err("protocol_version '2' is not a version like 2.8".to_owned()),
+        );
+        assert_eq!(
+            validate("protocol_version: '2.x'"),
+            Err("protocol_version '2.x' is not a version like 2.8".to_owned()),
+        );
+        assert_eq!(
+            validate("unique_id: 'AB-12'"),
+            Err("unique_id 'AB-12' is not valid, it should be letters and digits".to_owned()),
+        );
+        assert_eq!(validate("unique_id: ''"), Err("unique_id '' is not valid, it should be letters and digits".to_owned()));
+        assert_eq!(validate("model_name: ''"), Err("model_name is not valid".to_owned()));
+        assert_eq!(validate("friendly_name: \"A\\nB\""), Err("friendly_name is not valid".to_owned()));
+    }
+}
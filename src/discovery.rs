@@ -0,0 +1,31 @@
+use tokio::sync::Mutex;
+use tokio::time;
+use log::info;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::shared::{Shared};
+use crate::ndi::{Finder};
+
+/// How often the NDI finder is polled for sources appearing or disappearing.
+const POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Keeps polling the NDI finder for the lifetime of the router, feeding any
+/// change in the visible sources into the shared input table.
+pub async fn run(mut find: Box<dyn Finder>, state: Arc<Mutex<Shared>>) {
+    let mut interval = time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // A zero timeout just asks whether anything changed since last time.
+        if !find.wait_for_sources(0) {
+            continue;
+        }
+
+        let sources = find.current_sources();
+        info!("NDI sources changed, {} now visible", sources.len());
+
+        state.lock().await.update_sources(sources).await;
+    }
+}
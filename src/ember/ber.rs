@@ -0,0 +1,352 @@
+use std::fmt;
+
+pub const BOOLEAN: u32 = 1;
+pub const INTEGER: u32 = 2;
+pub const UTF8_STRING: u32 = 12;
+pub const RELATIVE_OID: u32 = 13;
+pub const SEQUENCE: u32 = 16;
+pub const SET: u32 = 17;
+
+/// How deeply values may nest, far deeper than Glow goes, so a hostile
+/// message cannot exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    Universal,
+    Application,
+    Context,
+    Private,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub class: Class,
+    pub number: u32,
+}
+
+impl Tag {
+    pub fn universal(number: u32) -> Tag {
+        Tag { class: Class::Universal, number }
+    }
+
+    pub fn application(number: u32) -> Tag {
+        Tag { class: Class::Application, number }
+    }
+
+    pub fn context(number: u32) -> Tag {
+        Tag { class: Class::Context, number }
+    }
+}
+
+/// A BER encoded value, either primitive bytes or a list of nested values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Primitive(Vec<u8>),
+    Constructed(Vec<Tlv>),
+}
+
+/// One tag, length and value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tlv {
+    pub tag: Tag,
+    pub value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BerError {
+    /// The input ended part way through a value.
+    Truncated,
+    /// The input is not valid BER, or uses a form this decoder does not
+    /// support.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for BerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BerError::Truncated => f.write_str("truncated BER value"),
+            BerError::Invalid(reason) => write!(f, "invalid BER value: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BerError {}
+
+impl Tlv {
+    pub fn constructed(tag: Tag, children: Vec<Tlv>) -> Tlv {
+        Tlv { tag, value: Value::Constructed(children) }
+    }
+
+    /// An explicitly tagged context value, as used for every field of a Glow
+    /// sequence or set.
+    pub fn context(number: u32, inner: Tlv) -> Tlv {
+        Tlv::constructed(Tag::context(number), vec![inner])
+    }
+
+    /// An application tag standing in for the sequence of `fields`, the
+    /// shape of every Glow type.
+    pub fn application(number: u32, fields: Vec<Tlv>) -> Tlv {
+        Tlv::constructed(Tag::application(number), fields)
+    }
+
+    pub fn sequence(children: Vec<Tlv>) -> Tlv {
+        Tlv::constructed(Tag::universal(SEQUENCE), children)
+    }
+
+    pub fn set(children: Vec<Tlv>) -> Tlv {
+        Tlv::constructed(Tag::universal(SET), children)
+    }
+
+    pub fn integer(n: i64) -> Tlv {
+        let bytes = n.to_be_bytes();
+
+        // The shortest two's complement form, dropping leading bytes that
+        // only repeat the sign.
+        let mut start = 0;
+        while start < bytes.len() - 1 {
+            let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+                || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0);
+            if !redundant {
+                break;
+            }
+            start += 1;
+        }
+
+        Tlv { tag: Tag::universal(INTEGER), value: Value::Primitive(bytes[start..].to_vec()) }
+    }
+
+    pub fn boolean(b: bool) -> Tlv {
+        Tlv { tag: Tag::universal(BOOLEAN), value: Value::Primitive(vec![if b { 0xFF } else { 0x00 }]) }
+    }
+
+    pub fn utf8(s: &str) -> Tlv {
+        Tlv { tag: Tag::universal(UTF8_STRING), value: Value::Primitive(s.as_bytes().to_vec()) }
+    }
+
+    pub fn relative_oid(numbers: &[u32]) -> Tlv {
+        let mut bytes = Vec::new();
+
+        for &n in numbers {
+            let mut groups = vec![(n & 0x7F) as u8];
+            let mut rest = n >> 7;
+            while rest > 0 {
+                groups.push((rest & 0x7F) as u8 | 0x80);
+                rest >>= 7;
+            }
+            bytes.extend(groups.into_iter().rev());
+        }
+
+        Tlv { tag: Tag::universal(RELATIVE_OID), value: Value::Primitive(bytes) }
+    }
+
+    pub fn children(&self) -> &[Tlv] {
+        match &self.value {
+            Value::Constructed(children) => children,
+            Value::Primitive(_) => &[],
+        }
+    }
+
+    /// The value inside the explicit context tag `number` among the children.
+    pub fn field(&self, number: u32) -> Option<&Tlv> {
+        self.children().iter()
+            .find(|child| child.tag == Tag::context(number))
+            .and_then(|field| field.children().first())
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match (&self.value, self.tag) {
+            (Value::Primitive(bytes), tag) if tag == Tag::universal(INTEGER) && !bytes.is_empty() && bytes.len() <= 8 => {
+                let sign = if bytes[0] & 0x80 != 0 { -1i64 } else { 0 };
+                Some(bytes.iter().fold(sign, |n, &byte| n << 8 | byte as i64))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_boolean(&self) -> Option<bool> {
+        match (&self.value, self.tag) {
+            (Value::Primitive(bytes), tag) if tag == Tag::universal(BOOLEAN) && bytes.len() == 1 => Some(bytes[0] != 0),
+            _ => None,
+        }
+    }
+
+    pub fn as_utf8(&self) -> Option<String> {
+        match (&self.value, self.tag) {
+            (Value::Primitive(bytes), tag) if tag == Tag::universal(UTF8_STRING) => {
+                Some(String::from_utf8_lossy(bytes).into_owned())
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_relative_oid(&self) -> Option<Vec<u32>> {
+        let bytes = match (&self.value, self.tag) {
+            (Value::Primitive(bytes), tag) if tag == Tag::universal(RELATIVE_OID) => bytes,
+            _ => return None,
+        };
+
+        let mut numbers = Vec::new();
+        let mut n: u32 = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            n = n.checked_mul(128)? | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                numbers.push(n);
+                n = 0;
+            } else if i == bytes.len() - 1 {
+                return None;
+            }
+        }
+
+        Some(numbers)
+    }
+
+    /// Encodes the value with definite lengths.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let (constructed, content) = match &self.value {
+            Value::Primitive(bytes) => (false, bytes.clone()),
+            Value::Constructed(children) => {
+                let mut content = Vec::new();
+                for child in children {
+                    child.encode(&mut content);
+                }
+                (true, content)
+            },
+        };
+
+        encode_tag(self.tag, constructed, out);
+        encode_length(content.len(), out);
+        out.extend_from_slice(&content);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+
+    /// Decodes one value from the start of `bytes`, returning it with the
+    /// number of bytes it took. Definite and indefinite lengths are accepted.
+    pub fn decode(bytes: &[u8]) -> Result<(Tlv, usize), BerError> {
+        decode(bytes, 0)
+    }
+}
+
+fn encode_tag(tag: Tag, constructed: bool, out: &mut Vec<u8>) {
+    let class = match tag.class {
+        Class::Universal => 0x00,
+        Class::Application => 0x40,
+        Class::Context => 0x80,
+        Class::Private => 0xC0,
+    };
+    let first = class | if constructed { 0x20 } else { 0x00 };
+
+    if tag.number < 31 {
+        out.push(first | tag.number as u8);
+        return;
+    }
+
+    out.push(first | 0x1F);
+    let mut groups = vec![(tag.number & 0x7F) as u8];
+    let mut rest = tag.number >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(groups.into_iter().rev());
+}
+
+fn encode_length(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+        return;
+    }
+
+    let bytes = (len as u64).to_be_bytes();
+    let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+    out.push(0x80 | (bytes.len() - skip) as u8);
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+fn decode(bytes: &[u8], depth: usize) -> Result<(Tlv, usize), BerError> {
+    if depth > MAX_DEPTH {
+        return Err(BerError::Invalid("nested too deeply"));
+    }
+
+    let first = *bytes.first().ok_or(BerError::Truncated)?;
+    let class = match first >> 6 {
+        0 => Class::Universal,
+        1 => Class::Application,
+        2 => Class::Context,
+        _ => Class::Private,
+    };
+    let constructed = first & 0x20 != 0;
+
+    let mut pos = 1;
+    let mut number = (first & 0x1F) as u32;
+    if number == 0x1F {
+        number = 0;
+        loop {
+            let byte = *bytes.get(pos).ok_or(BerError::Truncated)?;
+            pos += 1;
+            number = number.checked_mul(128).ok_or(BerError::Invalid("tag number too large"))? | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    let tag = Tag { class, number };
+
+    let len_byte = *bytes.get(pos).ok_or(BerError::Truncated)?;
+    pos += 1;
+
+    // Indefinite length, the contents run until two zero bytes.
+    if len_byte == 0x80 {
+        if !constructed {
+            return Err(BerError::Invalid("indefinite length on a primitive value"));
+        }
+
+        let mut children = Vec::new();
+        loop {
+            match bytes.get(pos..pos + 2) {
+                Some([0, 0]) => return Ok((Tlv::constructed(tag, children), pos + 2)),
+                Some(_) => {},
+                None => return Err(BerError::Truncated),
+            }
+
+            let (child, used) = decode(&bytes[pos..], depth + 1)?;
+            children.push(child);
+            pos += used;
+        }
+    }
+
+    let len = if len_byte & 0x80 == 0 {
+        len_byte as usize
+    } else {
+        let count = (len_byte & 0x7F) as usize;
+        if count > 4 {
+            return Err(BerError::Invalid("length too large"));
+        }
+
+        let len_bytes = bytes.get(pos..pos + count).ok_or(BerError::Truncated)?;
+        pos += count;
+        len_bytes.iter().fold(0usize, |len, &byte| len << 8 | byte as usize)
+    };
+
+    let content = bytes.get(pos..pos + len).ok_or(BerError::Truncated)?;
+    pos += len;
+
+    if !constructed {
+        return Ok((Tlv { tag, value: Value::Primitive(content.to_vec()) }, pos));
+    }
+
+    let mut children = Vec::new();
+    let mut offset = 0;
+    while offset < content.len() {
+        let (child, used) = decode(&content[offset..], depth + 1)?;
+        children.push(child);
+        offset += used;
+    }
+
+    Ok((Tlv::constructed(tag, children), pos))
+}
@@ -0,0 +1,399 @@
+pub mod ber;
+pub mod s101;
+pub mod server;
+
+pub use crate::ember::ber::{BerError, Tlv};
+pub use crate::ember::s101::{Packet, S101Codec};
+
+use crate::ember::ber::{Class, Tag};
+
+/// Application tags of the Glow types the router sends or understands.
+pub const ROOT: u32 = 0;
+pub const PARAMETER: u32 = 1;
+pub const COMMAND: u32 = 2;
+pub const NODE: u32 = 3;
+pub const ELEMENT_COLLECTION: u32 = 4;
+pub const QUALIFIED_PARAMETER: u32 = 9;
+pub const QUALIFIED_NODE: u32 = 10;
+pub const ROOT_ELEMENT_COLLECTION: u32 = 11;
+pub const MATRIX: u32 = 13;
+pub const CONNECTION: u32 = 16;
+pub const QUALIFIED_MATRIX: u32 = 17;
+pub const LABEL: u32 = 18;
+
+pub const COMMAND_SUBSCRIBE: i64 = 30;
+pub const COMMAND_UNSUBSCRIBE: i64 = 31;
+pub const COMMAND_GET_DIRECTORY: i64 = 32;
+
+/// Every target can take one source at a time.
+pub const MATRIX_TYPE_ONE_TO_N: i64 = 0;
+/// Targets and sources are numbered from 0 with no gaps.
+pub const ADDRESSING_LINEAR: i64 = 0;
+
+pub const ACCESS_READ: i64 = 1;
+pub const PARAMETER_TYPE_STRING: i64 = 3;
+
+pub const OPERATION_ABSOLUTE: i64 = 0;
+pub const OPERATION_CONNECT: i64 = 1;
+
+pub const DISPOSITION_TALLY: i64 = 0;
+pub const DISPOSITION_MODIFIED: i64 = 1;
+pub const DISPOSITION_PENDING: i64 = 2;
+pub const DISPOSITION_LOCKED: i64 = 3;
+
+/// Where an element is, either its number under the element it is sent in
+/// or its full path from the root.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Path {
+    Number(u32),
+    Qualified(Vec<u32>),
+}
+
+impl Path {
+    /// The full path of an element sent inside the one at `parent`.
+    pub fn resolve(&self, parent: &[u32]) -> Vec<u32> {
+        match self {
+            Path::Number(number) => parent.iter().cloned().chain(Some(*number)).collect(),
+            Path::Qualified(path) => path.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeContents {
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    pub is_root: Option<bool>,
+    pub is_online: Option<bool>,
+}
+
+/// Where a consumer finds the labels of a matrix's signals, under
+/// `base_path` with targets at `.1` and sources at `.2`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub base_path: Vec<u32>,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MatrixContents {
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    pub matrix_type: Option<i64>,
+    pub addressing_mode: Option<i64>,
+    pub target_count: Option<i64>,
+    pub source_count: Option<i64>,
+    pub labels: Vec<Label>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterValue {
+    Integer(i64),
+    String(String),
+    Boolean(bool),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParameterContents {
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    pub value: Option<ParameterValue>,
+    pub access: Option<i64>,
+    pub parameter_type: Option<i64>,
+}
+
+/// The sources on a matrix target, or a request to change them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Connection {
+    pub target: u32,
+    pub sources: Vec<u32>,
+    pub operation: Option<i64>,
+    pub disposition: Option<i64>,
+}
+
+/// The parts of the Glow tree the router publishes, and commands sent to
+/// them. Anything else a consumer sends is skipped when decoding.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    Command(i64),
+    Node {
+        path: Path,
+        contents: Option<NodeContents>,
+        children: Vec<Element>,
+    },
+    Matrix {
+        path: Path,
+        contents: Option<MatrixContents>,
+        children: Vec<Element>,
+        connections: Vec<Connection>,
+    },
+    Parameter {
+        path: Path,
+        contents: Option<ParameterContents>,
+        children: Vec<Element>,
+    },
+}
+
+/// Encodes `elements` as a Glow root, ready to be sent in an S101 packet.
+pub fn encode_root(elements: &[Element]) -> Vec<u8> {
+    let collection = Tlv::application(ROOT_ELEMENT_COLLECTION, elements.iter()
+        .map(|element| Tlv::context(0, encode_element(element)))
+        .collect());
+
+    Tlv::constructed(Tag::application(ROOT), vec![collection]).to_bytes()
+}
+
+/// Decodes the elements of a Glow root, skipping any the router does not
+/// model.
+pub fn decode_root(bytes: &[u8]) -> Result<Vec<Element>, BerError> {
+    let (root, _) = Tlv::decode(bytes)?;
+    if root.tag != Tag::application(ROOT) {
+        return Err(BerError::Invalid("not a Glow root"));
+    }
+
+    // Streams and invocation results are the other kinds of root, and carry
+    // nothing for a provider.
+    Ok(root.children().iter()
+        .filter(|child| child.tag == Tag::application(ROOT_ELEMENT_COLLECTION))
+        .flat_map(decode_collection)
+        .collect())
+}
+
+fn encode_element(element: &Element) -> Tlv {
+    match element {
+        Element::Command(number) => Tlv::application(COMMAND, vec![Tlv::context(0, Tlv::integer(*number))]),
+        Element::Node { path, contents, children } => {
+            let mut fields = vec![encode_path(path)];
+            if let Some(contents) = contents {
+                fields.push(Tlv::context(1, encode_node_contents(contents)));
+            }
+            fields.extend(encode_children(children));
+
+            Tlv::application(if let Path::Number(_) = path { NODE } else { QUALIFIED_NODE }, fields)
+        },
+        Element::Matrix { path, contents, children, connections } => {
+            let mut fields = vec![encode_path(path)];
+            if let Some(contents) = contents {
+                fields.push(Tlv::context(1, encode_matrix_contents(contents)));
+            }
+            fields.extend(encode_children(children));
+            if !connections.is_empty() {
+                fields.push(Tlv::context(5, Tlv::sequence(connections.iter()
+                    .map(|connection| Tlv::context(0, encode_connection(connection)))
+                    .collect())));
+            }
+
+            Tlv::application(if let Path::Number(_) = path { MATRIX } else { QUALIFIED_MATRIX }, fields)
+        },
+        Element::Parameter { path, contents, children } => {
+            let mut fields = vec![encode_path(path)];
+            if let Some(contents) = contents {
+                fields.push(Tlv::context(1, encode_parameter_contents(contents)));
+            }
+            fields.extend(encode_children(children));
+
+            Tlv::application(if let Path::Number(_) = path { PARAMETER } else { QUALIFIED_PARAMETER }, fields)
+        },
+    }
+}
+
+fn encode_path(path: &Path) -> Tlv {
+    match path {
+        Path::Number(number) => Tlv::context(0, Tlv::integer(*number as i64)),
+        Path::Qualified(path) => Tlv::context(0, Tlv::relative_oid(path)),
+    }
+}
+
+fn encode_children(children: &[Element]) -> Option<Tlv> {
+    if children.is_empty() {
+        return None;
+    }
+
+    Some(Tlv::context(2, Tlv::application(ELEMENT_COLLECTION, children.iter()
+        .map(|child| Tlv::context(0, encode_element(child)))
+        .collect())))
+}
+
+/// The set of `fields` that are present, each tagged with its number.
+fn contents_set(fields: Vec<(u32, Option<Tlv>)>) -> Tlv {
+    Tlv::set(fields.into_iter()
+        .filter_map(|(number, value)| value.map(|value| Tlv::context(number, value)))
+        .collect())
+}
+
+fn encode_node_contents(contents: &NodeContents) -> Tlv {
+    contents_set(vec![
+        (0, contents.identifier.as_ref().map(|s| Tlv::utf8(s))),
+        (1, contents.description.as_ref().map(|s| Tlv::utf8(s))),
+        (2, contents.is_root.map(Tlv::boolean)),
+        (3, contents.is_online.map(Tlv::boolean)),
+    ])
+}
+
+fn encode_matrix_contents(contents: &MatrixContents) -> Tlv {
+    let labels = if contents.labels.is_empty() {
+        None
+    } else {
+        Some(Tlv::sequence(contents.labels.iter()
+            .map(|label| Tlv::context(0, Tlv::application(LABEL, vec![
+                Tlv::context(0, Tlv::relative_oid(&label.base_path)),
+                Tlv::context(1, Tlv::utf8(&label.description)),
+            ])))
+            .collect()))
+    };
+
+    contents_set(vec![
+        (0, contents.identifier.as_ref().map(|s| Tlv::utf8(s))),
+        (1, contents.description.as_ref().map(|s| Tlv::utf8(s))),
+        (2, contents.matrix_type.map(Tlv::integer)),
+        (3, contents.addressing_mode.map(Tlv::integer)),
+        (4, contents.target_count.map(Tlv::integer)),
+        (5, contents.source_count.map(Tlv::integer)),
+        (10, labels),
+    ])
+}
+
+fn encode_parameter_contents(contents: &ParameterContents) -> Tlv {
+    let value = contents.value.as_ref().map(|value| match value {
+        ParameterValue::Integer(n) => Tlv::integer(*n),
+        ParameterValue::String(s) => Tlv::utf8(s),
+        ParameterValue::Boolean(b) => Tlv::boolean(*b),
+    });
+
+    contents_set(vec![
+        (0, contents.identifier.as_ref().map(|s| Tlv::utf8(s))),
+        (1, contents.description.as_ref().map(|s| Tlv::utf8(s))),
+        (2, value),
+        (5, contents.access.map(Tlv::integer)),
+        (13, contents.parameter_type.map(Tlv::integer)),
+    ])
+}
+
+fn encode_connection(connection: &Connection) -> Tlv {
+    let mut fields = vec![
+        Tlv::context(0, Tlv::integer(connection.target as i64)),
+        Tlv::context(1, Tlv::relative_oid(&connection.sources)),
+    ];
+    if let Some(operation) = connection.operation {
+        fields.push(Tlv::context(2, Tlv::integer(operation)));
+    }
+    if let Some(disposition) = connection.disposition {
+        fields.push(Tlv::context(3, Tlv::integer(disposition)));
+    }
+
+    Tlv::application(CONNECTION, fields)
+}
+
+/// The elements of a root or element collection, each in a `[0]` tag.
+fn decode_collection(collection: &Tlv) -> Vec<Element> {
+    collection.children().iter()
+        .filter(|child| child.tag == Tag::context(0))
+        .filter_map(|child| child.children().first())
+        .filter_map(decode_element)
+        .collect()
+}
+
+fn decode_element(tlv: &Tlv) -> Option<Element> {
+    if tlv.tag.class != Class::Application {
+        return None;
+    }
+
+    let children = || tlv.field(2).map(decode_collection).unwrap_or_default();
+
+    match tlv.tag.number {
+        COMMAND => Some(Element::Command(tlv.field(0)?.as_integer()?)),
+        NODE | QUALIFIED_NODE => Some(Element::Node {
+            path: decode_path(tlv)?,
+            contents: tlv.field(1).map(decode_node_contents),
+            children: children(),
+        }),
+        MATRIX | QUALIFIED_MATRIX => Some(Element::Matrix {
+            path: decode_path(tlv)?,
+            contents: tlv.field(1).map(decode_matrix_contents),
+            children: children(),
+            connections: tlv.field(5)
+                .map(|connections| connections.children().iter()
+                    .filter_map(|connection| connection.children().first())
+                    .filter_map(decode_connection)
+                    .collect())
+                .unwrap_or_default(),
+        }),
+        PARAMETER | QUALIFIED_PARAMETER => Some(Element::Parameter {
+            path: decode_path(tlv)?,
+            contents: tlv.field(1).map(decode_parameter_contents),
+            children: children(),
+        }),
+        _ => None,
+    }
+}
+
+fn decode_path(tlv: &Tlv) -> Option<Path> {
+    let field = tlv.field(0)?;
+
+    match tlv.tag.number {
+        QUALIFIED_NODE | QUALIFIED_MATRIX | QUALIFIED_PARAMETER => Some(Path::Qualified(field.as_relative_oid()?)),
+        _ => Some(Path::Number(field.as_integer().filter(|&n| n >= 0 && n <= u32::MAX as i64)? as u32)),
+    }
+}
+
+fn decode_node_contents(set: &Tlv) -> NodeContents {
+    NodeContents {
+        identifier: set.field(0).and_then(Tlv::as_utf8),
+        description: set.field(1).and_then(Tlv::as_utf8),
+        is_root: set.field(2).and_then(Tlv::as_boolean),
+        is_online: set.field(3).and_then(Tlv::as_boolean),
+    }
+}
+
+fn decode_matrix_contents(set: &Tlv) -> MatrixContents {
+    let labels = set.field(10)
+        .map(|labels| labels.children().iter()
+            .filter_map(|label| label.children().first())
+            .filter_map(|label| Some(Label {
+                base_path: label.field(0)?.as_relative_oid()?,
+                description: label.field(1).and_then(Tlv::as_utf8).unwrap_or_default(),
+            }))
+            .collect())
+        .unwrap_or_default();
+
+    MatrixContents {
+        identifier: set.field(0).and_then(Tlv::as_utf8),
+        description: set.field(1).and_then(Tlv::as_utf8),
+        matrix_type: set.field(2).and_then(Tlv::as_integer),
+        addressing_mode: set.field(3).and_then(Tlv::as_integer),
+        target_count: set.field(4).and_then(Tlv::as_integer),
+        source_count: set.field(5).and_then(Tlv::as_integer),
+        labels,
+    }
+}
+
+fn decode_parameter_contents(set: &Tlv) -> ParameterContents {
+    let value = set.field(2).and_then(|value| {
+        value.as_integer().map(ParameterValue::Integer)
+            .or_else(|| value.as_utf8().map(ParameterValue::String))
+            .or_else(|| value.as_boolean().map(ParameterValue::Boolean))
+    });
+
+    ParameterContents {
+        identifier: set.field(0).and_then(Tlv::as_utf8),
+        description: set.field(1).and_then(Tlv::as_utf8),
+        value,
+        access: set.field(5).and_then(Tlv::as_integer),
+        parameter_type: set.field(13).and_then(Tlv::as_integer),
+    }
+}
+
+fn decode_connection(tlv: &Tlv) -> Option<Connection> {
+    if tlv.tag != Tag::application(CONNECTION) {
+        return None;
+    }
+
+    let target = tlv.field(0)?.as_integer().filter(|&n| n >= 0 && n <= u32::MAX as i64)? as u32;
+
+    Some(Connection {
+        target,
+        sources: tlv.field(1).and_then(Tlv::as_relative_oid).unwrap_or_default(),
+        operation: tlv.field(2).and_then(Tlv::as_integer),
+        disposition: tlv.field(3).and_then(Tlv::as_integer),
+    })
+}
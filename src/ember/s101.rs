@@ -0,0 +1,222 @@
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use std::io;
+
+pub const BOF: u8 = 0xFE;
+pub const EOF: u8 = 0xFF;
+/// Escapes any byte from `0xF8` up inside a frame, which is sent as `CE`
+/// followed by the byte XORed with `XOR`.
+pub const CE: u8 = 0xFD;
+const XOR: u8 = 0x20;
+
+const SLOT: u8 = 0x00;
+const MESSAGE_EMBER: u8 = 0x0E;
+const COMMAND_EMBER: u8 = 0x00;
+const COMMAND_KEEP_ALIVE_REQUEST: u8 = 0x01;
+const COMMAND_KEEP_ALIVE_RESPONSE: u8 = 0x02;
+const VERSION: u8 = 0x01;
+const DTD_GLOW: u8 = 0x01;
+/// Glow DTD version 2.31, minor then major.
+const GLOW_VERSION: [u8; 2] = [0x1F, 0x02];
+
+const FLAG_FIRST: u8 = 0x80;
+const FLAG_LAST: u8 = 0x40;
+const FLAG_EMPTY: u8 = 0x20;
+
+/// Most Glow bytes sent in one frame, longer messages are split.
+const MAX_PACKET_DATA: usize = 1024;
+
+/// Longest frame or message accepted, anything longer is a broken consumer.
+const MAX_MESSAGE_LENGTH: usize = 1024 * 1024;
+
+/// What is sent on an Ember+ connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    /// A whole Glow message, put back together if it was split over frames.
+    Ember(Vec<u8>),
+    KeepAliveRequest,
+    KeepAliveResponse,
+}
+
+/// Frames a byte stream into S101 packets.
+///
+/// Each frame is `BOF`, the escaped payload and its CRC, then `EOF`. Frames
+/// that fail their CRC, and frames that are not Ember+ messages, are dropped.
+pub struct S101Codec {
+    /// Glow data of a message split over several frames, until its last.
+    partial: Option<Vec<u8>>,
+}
+
+impl S101Codec {
+    pub fn new() -> S101Codec {
+        S101Codec { partial: None }
+    }
+
+    fn unpack(&mut self, payload: &[u8]) -> Option<Packet> {
+        match payload {
+            [SLOT, MESSAGE_EMBER, COMMAND_KEEP_ALIVE_REQUEST, VERSION, ..] => Some(Packet::KeepAliveRequest),
+            [SLOT, MESSAGE_EMBER, COMMAND_KEEP_ALIVE_RESPONSE, VERSION, ..] => Some(Packet::KeepAliveResponse),
+            [SLOT, MESSAGE_EMBER, COMMAND_EMBER, VERSION, flags, DTD_GLOW, app_bytes, rest @ ..] => {
+                let data = rest.get(*app_bytes as usize..)?;
+
+                if flags & FLAG_EMPTY != 0 {
+                    return None;
+                }
+
+                if flags & FLAG_FIRST != 0 {
+                    self.partial = Some(Vec::new());
+                }
+
+                let partial = self.partial.as_mut()?;
+                partial.extend_from_slice(data);
+                if partial.len() > MAX_MESSAGE_LENGTH {
+                    self.partial = None;
+                    return None;
+                }
+
+                if flags & FLAG_LAST != 0 {
+                    self.partial.take().map(Packet::Ember)
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Default for S101Codec {
+    fn default() -> Self {
+        S101Codec::new()
+    }
+}
+
+/// CRC-CCITT as S101 uses it, reflected and inverted.
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+impl Decoder for S101Codec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Packet>, io::Error> {
+        loop {
+            match src.iter().position(|&byte| byte == BOF) {
+                Some(start) => src.advance(start),
+                None => {
+                    src.clear();
+                    return Ok(None);
+                },
+            }
+
+            let end = match src.iter().position(|&byte| byte == EOF) {
+                Some(end) => end,
+                None if src.len() > MAX_MESSAGE_LENGTH * 2 => {
+                    src.clear();
+                    return Ok(None);
+                },
+                None => return Ok(None),
+            };
+
+            // A BOF part way through starts a new frame, this one was cut off.
+            if let Some(restart) = src[1..end].iter().rposition(|&byte| byte == BOF) {
+                src.advance(restart + 1);
+                continue;
+            }
+
+            let frame = src.split_to(end + 1);
+            let frame = &frame[1..end];
+
+            let mut payload = Vec::with_capacity(frame.len());
+            let mut escaped = false;
+            for &byte in frame {
+                if escaped {
+                    payload.push(byte ^ XOR);
+                    escaped = false;
+                } else if byte == CE {
+                    escaped = true;
+                } else {
+                    payload.push(byte);
+                }
+            }
+
+            if escaped || payload.len() < 2 {
+                continue;
+            }
+
+            let (payload, checksum) = payload.split_at(payload.len() - 2);
+            if crc(payload) != u16::from_le_bytes([checksum[0], checksum[1]]) {
+                continue;
+            }
+
+            if let Some(packet) = self.unpack(payload) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
+
+impl Encoder for S101Codec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let data = match packet {
+            Packet::Ember(data) => data,
+            Packet::KeepAliveRequest => {
+                put_frame(&[SLOT, MESSAGE_EMBER, COMMAND_KEEP_ALIVE_REQUEST, VERSION], dst);
+                return Ok(());
+            },
+            Packet::KeepAliveResponse => {
+                put_frame(&[SLOT, MESSAGE_EMBER, COMMAND_KEEP_ALIVE_RESPONSE, VERSION], dst);
+                return Ok(());
+            },
+        };
+
+        let chunks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(MAX_PACKET_DATA).collect() };
+        let last = chunks.len() - 1;
+
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let mut flags = 0;
+            if i == 0 {
+                flags |= FLAG_FIRST;
+            }
+            if i == last {
+                flags |= FLAG_LAST;
+            }
+
+            let mut payload = vec![SLOT, MESSAGE_EMBER, COMMAND_EMBER, VERSION, flags, DTD_GLOW, GLOW_VERSION.len() as u8];
+            payload.extend_from_slice(&GLOW_VERSION);
+            payload.extend_from_slice(chunk);
+            put_frame(&payload, dst);
+        }
+
+        Ok(())
+    }
+}
+
+fn put_frame(payload: &[u8], dst: &mut BytesMut) {
+    dst.reserve(payload.len() * 2 + 6);
+    dst.put_u8(BOF);
+
+    for &byte in payload.iter().chain(&crc(payload).to_le_bytes()) {
+        if byte >= 0xF8 {
+            dst.put_u8(CE);
+            dst.put_u8(byte ^ XOR);
+        } else {
+            dst.put_u8(byte);
+        }
+    }
+
+    dst.put_u8(EOF);
+}
@@ -0,0 +1,390 @@
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::RecvError;
+use tokio_util::codec::{Framed};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info};
+use std::{error::Error, io};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::events::{Event};
+use crate::shared::{Shared};
+use crate::ember::{
+    self, Connection, Element, Label, MatrixContents, NodeContents, Packet, ParameterContents,
+    ParameterValue, Path, S101Codec,
+};
+
+/// Where everything the router publishes lives in the Glow tree.
+pub const ROUTER: &[u32] = &[1];
+pub const MATRIX: &[u32] = &[1, 1];
+pub const LABELS: &[u32] = &[1, 2];
+pub const TARGET_LABELS: &[u32] = &[1, 2, 1];
+pub const SOURCE_LABELS: &[u32] = &[1, 2, 2];
+
+/// Accepts Ember+ consumers on `listener` until it fails.
+pub async fn serve(mut listener: TcpListener, state: Arc<Mutex<Shared>>) -> io::Result<()> {
+    info!("Ember+ provider running on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let state = Arc::clone(&state);
+
+        tokio::spawn(async move {
+            if let Err(e) = process(state, stream, addr).await {
+                info!("Ember+ consumer {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Serves one Ember+ consumer until it disconnects.
+///
+/// The router is published as a one to N matrix with outputs as targets and
+/// inputs as sources, labelled by string parameters under `LABELS`.
+/// Connections are made through the router core on behalf of the consumer's
+/// address, so Videohub locks apply to it too. Every change is sent to every
+/// consumer whether or not it subscribed, as the tree is small.
+pub async fn process(
+    state: Arc<Mutex<Shared>>,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> Result<(), Box<dyn Error>> {
+    info!("New Ember+ consumer connected: {}", addr);
+
+    let mut packets = Framed::new(stream, S101Codec::new());
+    let (mut events, mut num_inputs) = {
+        let state = state.lock().await;
+        (state.events.subscribe(), state.inputs.len())
+    };
+
+    loop {
+        tokio::select! {
+            packet = packets.next() => match packet {
+                Some(Ok(Packet::Ember(data))) => {
+                    let elements = match ember::decode_root(&data) {
+                        Ok(elements) => elements,
+                        Err(e) => {
+                            debug!("undecodable Glow message from {}: {}", addr, e);
+                            continue;
+                        },
+                    };
+
+                    debug!("Ember+ {:?} from {}", elements, addr);
+                    for reply in handle(&state, addr, &elements).await {
+                        packets.send(Packet::Ember(ember::encode_root(&[reply]))).await?;
+                    }
+                },
+                Some(Ok(Packet::KeepAliveRequest)) => packets.send(Packet::KeepAliveResponse).await?,
+                Some(Ok(Packet::KeepAliveResponse)) => {},
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+            event = events.recv() => match event {
+                Ok(Event::Routes(routes)) => {
+                    let connections = routes.into_iter()
+                        .map(|(target, source)| connection(target, Some(source), ember::DISPOSITION_MODIFIED))
+                        .collect();
+                    packets.send(Packet::Ember(ember::encode_root(&[matrix_connections(connections)]))).await?;
+                },
+                Ok(Event::Pending(routes)) => {
+                    let connections = routes.into_iter()
+                        .map(|(target, source)| connection(target, Some(source), ember::DISPOSITION_PENDING))
+                        .collect();
+                    packets.send(Packet::Ember(ember::encode_root(&[matrix_connections(connections)]))).await?;
+                },
+                Ok(Event::OutputLabels(labels)) => {
+                    packets.send(Packet::Ember(ember::encode_root(&label_updates(TARGET_LABELS, &labels)))).await?;
+                },
+                Ok(Event::InputLabels(labels)) => {
+                    packets.send(Packet::Ember(ember::encode_root(&label_updates(SOURCE_LABELS, &labels)))).await?;
+                },
+                // Inputs are added as new sources are discovered, which
+                // changes the size of the matrix.
+                Ok(Event::Source { .. }) => {
+                    let update = {
+                        let state = state.lock().await;
+                        if state.inputs.len() == num_inputs {
+                            None
+                        } else {
+                            num_inputs = state.inputs.len();
+                            Some(Element::Matrix {
+                                path: Path::Qualified(MATRIX.to_vec()),
+                                contents: Some(matrix_contents(&state)),
+                                children: Vec::new(),
+                                connections: Vec::new(),
+                            })
+                        }
+                    };
+
+                    if let Some(update) = update {
+                        packets.send(Packet::Ember(ember::encode_root(&[update]))).await?;
+                    }
+                },
+                Ok(_) => {},
+                // Changes were missed, so the consumer is sent the whole
+                // matrix and every label again.
+                Err(RecvError::Lagged(_)) => {
+                    let elements = {
+                        let state = state.lock().await;
+                        events = state.events.subscribe();
+                        num_inputs = state.inputs.len();
+                        [MATRIX, TARGET_LABELS, SOURCE_LABELS].iter()
+                            .filter_map(|path| directory(&state, path))
+                            .collect::<Vec<_>>()
+                    };
+
+                    packets.send(Packet::Ember(ember::encode_root(&elements))).await?;
+                },
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+
+    info!("Ember+ consumer {} disconnected", addr);
+    Ok(())
+}
+
+/// What a consumer asked for in one message.
+#[derive(Debug, PartialEq)]
+enum Request {
+    GetDirectory(Vec<u32>),
+    Connect(Vec<u32>, Vec<Connection>),
+}
+
+/// Finds every request in `elements`, which are sent inside the element at
+/// `parent`. Commands and connections may come inside nested elements or
+/// qualified ones.
+fn requests(elements: &[Element], parent: &[u32], found: &mut Vec<Request>) {
+    for element in elements {
+        match element {
+            Element::Command(ember::COMMAND_GET_DIRECTORY) => found.push(Request::GetDirectory(parent.to_vec())),
+            // Every consumer is sent every change anyway.
+            Element::Command(_) => {},
+            Element::Node { path, children, .. } | Element::Parameter { path, children, .. } => {
+                requests(children, &path.resolve(parent), found);
+            },
+            Element::Matrix { path, children, connections, .. } => {
+                let path = path.resolve(parent);
+                if !connections.is_empty() {
+                    found.push(Request::Connect(path.clone(), connections.clone()));
+                }
+                requests(children, &path, found);
+            },
+        }
+    }
+}
+
+/// Acts on a message from a consumer, returning the root elements of the
+/// replies to send it.
+async fn handle(state: &Arc<Mutex<Shared>>, addr: SocketAddr, elements: &[Element]) -> Vec<Element> {
+    let mut found = Vec::new();
+    requests(elements, &[], &mut found);
+
+    let mut state = state.lock().await;
+    let mut replies = Vec::new();
+
+    for request in found {
+        match request {
+            Request::GetDirectory(path) => replies.extend(directory(&state, &path)),
+            Request::Connect(path, connections) if path == MATRIX => {
+                let mut refused = Vec::new();
+
+                for requested in connections {
+                    let target = requested.target as usize;
+
+                    // Only absolute connections of one source can be made,
+                    // there is no way to leave an output with nothing on it.
+                    let source = match (requested.operation.unwrap_or(ember::OPERATION_ABSOLUTE), &requested.sources[..]) {
+                        (ember::OPERATION_ABSOLUTE, [source]) | (ember::OPERATION_CONNECT, [source]) => *source as usize,
+                        _ => {
+                            refused.push(target);
+                            continue;
+                        },
+                    };
+
+                    // An accepted connection is confirmed by the change sent
+                    // to every consumer.
+                    if !state.apply_routes(addr, &[(target, source)]).await {
+                        debug!("refused Ember+ connection of source {} to target {} from {}", source, target, addr);
+                        refused.push(target);
+                    }
+                }
+
+                // A refused connection gets the one the target is left with.
+                let connections: Vec<Connection> = refused.into_iter()
+                    .filter(|&target| target < state.outputs.len())
+                    .map(|target| {
+                        let disposition = if state.video_hub.is_locked_by_other(target, addr) {
+                            ember::DISPOSITION_LOCKED
+                        } else {
+                            ember::DISPOSITION_TALLY
+                        };
+                        connection(target, state.video_hub.routes().get(&target).cloned(), disposition)
+                    })
+                    .collect();
+
+                if !connections.is_empty() {
+                    replies.push(matrix_connections(connections));
+                }
+            },
+            Request::Connect(path, _) => debug!("Ember+ connection to {:?} from {}, which is not a matrix", path, addr),
+        }
+    }
+
+    replies
+}
+
+/// The reply to a `getDirectory` of `path`: the element with its children,
+/// or `None` if there is no such element.
+fn directory(state: &Shared, path: &[u32]) -> Option<Element> {
+    let qualified = Path::Qualified(path.to_vec());
+
+    match path {
+        [] => Some(Element::Node {
+            path: Path::Number(ROUTER[0]),
+            contents: Some(router_contents(state)),
+            children: Vec::new(),
+        }),
+        _ if path == ROUTER => Some(Element::Node {
+            path: qualified,
+            contents: Some(router_contents(state)),
+            children: vec![
+                Element::Matrix {
+                    path: Path::Number(MATRIX[1]),
+                    contents: Some(matrix_contents(state)),
+                    children: Vec::new(),
+                    connections: Vec::new(),
+                },
+                node(LABELS[1], "labels", "Signal labels"),
+            ],
+        }),
+        _ if path == MATRIX => Some(Element::Matrix {
+            path: qualified,
+            contents: Some(matrix_contents(state)),
+            children: Vec::new(),
+            connections: (0..state.outputs.len())
+                .map(|target| connection(target, state.video_hub.routes().get(&target).cloned(), ember::DISPOSITION_TALLY))
+                .collect(),
+        }),
+        _ if path == LABELS => Some(Element::Node {
+            path: qualified,
+            contents: Some(node_contents("labels", "Signal labels")),
+            children: vec![
+                node(TARGET_LABELS[2], "targets", "Outputs"),
+                node(SOURCE_LABELS[2], "sources", "Inputs"),
+            ],
+        }),
+        _ if path == TARGET_LABELS || path == SOURCE_LABELS => {
+            let (identifier, description) = if path == TARGET_LABELS { ("targets", "Outputs") } else { ("sources", "Inputs") };
+
+            Some(Element::Node {
+                path: qualified,
+                contents: Some(node_contents(identifier, description)),
+                children: labels(state, path).iter().enumerate()
+                    .map(|(n, label)| label_parameter(Path::Number(n as u32), path, n, label))
+                    .collect(),
+            })
+        },
+        [parent @ .., n] if parent == TARGET_LABELS || parent == SOURCE_LABELS => {
+            let label = labels(state, parent).get(*n as usize)?;
+            Some(label_parameter(qualified, parent, *n as usize, label))
+        },
+        _ => None,
+    }
+}
+
+fn labels<'a>(state: &'a Shared, path: &[u32]) -> &'a [String] {
+    if path == TARGET_LABELS {
+        state.video_hub.output_labels()
+    } else {
+        state.video_hub.input_labels()
+    }
+}
+
+fn router_contents(state: &Shared) -> NodeContents {
+    NodeContents {
+        is_root: Some(true),
+        is_online: Some(true),
+        ..node_contents("router", &state.video_hub.identity().friendly_name)
+    }
+}
+
+fn node_contents(identifier: &str, description: &str) -> NodeContents {
+    NodeContents {
+        identifier: Some(identifier.to_owned()),
+        description: Some(description.to_owned()),
+        ..NodeContents::default()
+    }
+}
+
+fn node(number: u32, identifier: &str, description: &str) -> Element {
+    Element::Node {
+        path: Path::Number(number),
+        contents: Some(node_contents(identifier, description)),
+        children: Vec::new(),
+    }
+}
+
+fn matrix_contents(state: &Shared) -> MatrixContents {
+    MatrixContents {
+        identifier: Some("matrix".to_owned()),
+        description: Some("NDI routing".to_owned()),
+        matrix_type: Some(ember::MATRIX_TYPE_ONE_TO_N),
+        addressing_mode: Some(ember::ADDRESSING_LINEAR),
+        target_count: Some(state.outputs.len() as i64),
+        source_count: Some(state.inputs.len() as i64),
+        labels: vec![Label { base_path: LABELS.to_vec(), description: "Labels".to_owned() }],
+    }
+}
+
+fn matrix_connections(connections: Vec<Connection>) -> Element {
+    Element::Matrix {
+        path: Path::Qualified(MATRIX.to_vec()),
+        contents: None,
+        children: Vec::new(),
+        connections,
+    }
+}
+
+/// The source on `target`, none if it has never been routed.
+fn connection(target: usize, source: Option<usize>, disposition: i64) -> Connection {
+    Connection {
+        target: target as u32,
+        sources: source.into_iter().map(|source| source as u32).collect(),
+        operation: None,
+        disposition: Some(disposition),
+    }
+}
+
+/// The string parameter holding the label of signal `n` under `parent`.
+fn label_parameter(path: Path, parent: &[u32], n: usize, label: &str) -> Element {
+    let prefix = if parent == TARGET_LABELS { "t" } else { "s" };
+
+    Element::Parameter {
+        path,
+        contents: Some(ParameterContents {
+            identifier: Some(format!("{}-{}", prefix, n)),
+            value: Some(ParameterValue::String(label.to_owned())),
+            access: Some(ember::ACCESS_READ),
+            parameter_type: Some(ember::PARAMETER_TYPE_STRING),
+            ..ParameterContents::default()
+        }),
+        children: Vec::new(),
+    }
+}
+
+/// New values for the label parameters of the changed signals under
+/// `parent`.
+fn label_updates(parent: &[u32], labels: &[(usize, String)]) -> Vec<Element> {
+    labels.iter()
+        .map(|(n, label)| Element::Parameter {
+            path: Path::Qualified(parent.iter().cloned().chain(Some(*n as u32)).collect()),
+            contents: Some(ParameterContents {
+                value: Some(ParameterValue::String(label.clone())),
+                ..ParameterContents::default()
+            }),
+            children: Vec::new(),
+        })
+        .collect()
+}
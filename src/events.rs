@@ -0,0 +1,39 @@
+use tokio::sync::broadcast;
+use std::net::SocketAddr;
+
+/// How many events are kept for a subscriber that has fallen behind before it
+/// starts missing them.
+pub const BACKLOG: usize = 256;
+
+/// Shorthand for the sending half of the event channel.
+pub type EventTx = broadcast::Sender<Event>;
+
+/// Shorthand for the receiving half of the event channel.
+pub type EventRx = broadcast::Receiver<Event>;
+
+/// A change to the router, published to anything watching it other than
+/// Videohub controllers, which get the same changes as protocol blocks.
+///
+/// Events are not tied to a client, so locks carry their owner rather than
+/// a state relative to whoever receives them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The input now routed to each of some outputs.
+    Routes(Vec<(usize, usize)>),
+    /// Every crosspoint waiting to be taken.
+    Pending(Vec<(usize, usize)>),
+    /// Whether each of some outputs holds new routes until they are taken.
+    TakeModes(Vec<(usize, bool)>),
+    InputLabels(Vec<(usize, String)>),
+    OutputLabels(Vec<(usize, String)>),
+    /// The client holding the lock on each of some outputs.
+    Locks(Vec<(usize, Option<SocketAddr>)>),
+    /// An input's NDI source appeared on or went away from the network.
+    Source { input: usize, ndi_name: String, ip: String, online: bool },
+    ControllerConnected(SocketAddr),
+    ControllerDisconnected(SocketAddr),
+}
+
+pub fn channel() -> EventTx {
+    broadcast::channel(BACKLOG).0
+}
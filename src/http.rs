@@ -0,0 +1,504 @@
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::server::accept;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::upgrade::Upgraded;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::RecvError;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::tungstenite::handshake::server::create_response;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use futures::{SinkExt, StreamExt};
+use log::{debug, info};
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::shared::{Shared};
+use crate::videohub::{LockState};
+use crate::events;
+use crate::panel;
+
+/// An input as listed by the API.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Input {
+    pub index: usize,
+    pub label: String,
+    /// The NDI source assigned to the input, `None` for a free slot.
+    pub ndi_name: Option<String>,
+    pub ip: Option<String>,
+    pub online: bool,
+}
+
+/// An output as listed by the API.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    pub index: usize,
+    pub label: String,
+    pub ndi_name: String,
+    /// The input routed to the output, if any.
+    pub input: Option<usize>,
+    /// The lock as seen by the API, `owned` when it was locked over HTTP.
+    pub lock: LockState,
+    pub take_mode: bool,
+    /// The input waiting to be taken on the output, if any.
+    pub pending: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Crosspoint {
+    pub output: usize,
+    pub input: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Label {
+    pub label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lock {
+    pub lock: LockState,
+}
+
+/// Outputs to take, every output with a pending route when empty.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Take {
+    pub outputs: Vec<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiError {
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortLabel {
+    pub index: usize,
+    pub label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutputLock {
+    pub output: usize,
+    pub lock: LockState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TakeMode {
+    pub output: usize,
+    pub take_mode: bool,
+}
+
+/// A message on the event stream, tagged with its `type`. Changes only carry
+/// the ports that changed, in the same form as the rest of the API.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The whole router, sent when the stream starts and again if it falls
+    /// too far behind to catch up.
+    Snapshot {
+        inputs: Vec<Input>,
+        outputs: Vec<Output>,
+        /// Connected Videohub controllers.
+        controllers: Vec<SocketAddr>,
+    },
+    Routes { routes: Vec<Crosspoint> },
+    /// Every crosspoint waiting to be taken.
+    Pending { routes: Vec<Crosspoint> },
+    TakeModes { outputs: Vec<TakeMode> },
+    InputLabels { labels: Vec<PortLabel> },
+    OutputLabels { labels: Vec<PortLabel> },
+    Locks { locks: Vec<OutputLock> },
+    /// An input's NDI source appeared on or went away from the network.
+    Source { index: usize, ndi_name: String, ip: Option<String>, online: bool },
+    ControllerConnected { addr: SocketAddr },
+    ControllerDisconnected { addr: SocketAddr },
+}
+
+impl Event {
+    /// The API form of a router event, with locks as seen by `api`.
+    fn new(event: events::Event, api: SocketAddr) -> Event {
+        let crosspoints = |routes: Vec<(usize, usize)>| routes.into_iter()
+            .map(|(output, input)| Crosspoint { output, input })
+            .collect();
+        let labels = |labels: Vec<(usize, String)>| labels.into_iter()
+            .map(|(index, label)| PortLabel { index, label })
+            .collect();
+
+        match event {
+            events::Event::Routes(routes) => Event::Routes { routes: crosspoints(routes) },
+            events::Event::Pending(routes) => Event::Pending { routes: crosspoints(routes) },
+            events::Event::TakeModes(outputs) => Event::TakeModes {
+                outputs: outputs.into_iter()
+                    .map(|(output, take_mode)| TakeMode { output, take_mode })
+                    .collect(),
+            },
+            events::Event::InputLabels(changed) => Event::InputLabels { labels: labels(changed) },
+            events::Event::OutputLabels(changed) => Event::OutputLabels { labels: labels(changed) },
+            events::Event::Locks(owners) => Event::Locks {
+                locks: owners.into_iter()
+                    .map(|(output, owner)| OutputLock {
+                        output,
+                        lock: match owner {
+                            Some(owner) if owner == api => LockState::Owned,
+                            Some(_) => LockState::Locked,
+                            None => LockState::Unlocked,
+                        },
+                    })
+                    .collect(),
+            },
+            events::Event::Source { input, ndi_name, ip, online } => Event::Source {
+                index: input,
+                ndi_name,
+                ip: Some(ip).filter(|ip| !ip.is_empty()),
+                online,
+            },
+            events::Event::ControllerConnected(addr) => Event::ControllerConnected { addr },
+            events::Event::ControllerDisconnected(addr) => Event::ControllerDisconnected { addr },
+        }
+    }
+}
+
+/// Serves the JSON API, and the web control panel built on it, on `listener`
+/// until it fails.
+///
+/// Changes go through the same router core as the Videohub server, so they
+/// are sent on to every connected panel, and every change made by anyone is
+/// streamed to WebSocket clients of `/api/events`. HTTP requests do not
+/// belong to a connection, so locks taken over HTTP are held by the API as a
+/// whole, with the listening address standing in for the client.
+pub async fn serve(mut listener: TcpListener, state: Arc<Mutex<Shared>>) -> io::Result<()> {
+    let api = listener.local_addr()?;
+    info!("HTTP API running on {}", api);
+
+    let make_service = make_service_fn(move |_| {
+        let state = Arc::clone(&state);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&state), api, req)))
+        }
+    });
+
+    Server::builder(accept::from_stream(listener.incoming()))
+        .serve(make_service)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+async fn handle(state: Arc<Mutex<Shared>>, api: SocketAddr, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    debug!("HTTP {} {}", req.method(), req.uri().path());
+
+    let path: Vec<String> = req.uri().path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.to_owned())
+        .collect();
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    let method = req.method().clone();
+
+    let response = match (method, path.as_slice()) {
+        (Method::GET, ["api", "inputs"]) => {
+            let state = state.lock().await;
+            json(StatusCode::OK, &inputs(&state))
+        },
+        (Method::GET, ["api", "inputs", index]) => match index.parse::<usize>() {
+            Ok(index) => {
+                let state = state.lock().await;
+                match inputs(&state).into_iter().nth(index) {
+                    Some(input) => json(StatusCode::OK, &input),
+                    None => error(StatusCode::NOT_FOUND, "no such input"),
+                }
+            },
+            Err(_) => error(StatusCode::NOT_FOUND, "no such input"),
+        },
+        (Method::PUT, ["api", "inputs", index, "label"]) => {
+            set_label(state, api, req, index, true).await
+        },
+        (Method::GET, ["api", "outputs"]) => {
+            let state = state.lock().await;
+            json(StatusCode::OK, &outputs(&state, api))
+        },
+        (Method::GET, ["api", "outputs", index]) => match index.parse::<usize>() {
+            Ok(index) => {
+                let state = state.lock().await;
+                match outputs(&state, api).into_iter().nth(index) {
+                    Some(output) => json(StatusCode::OK, &output),
+                    None => error(StatusCode::NOT_FOUND, "no such output"),
+                }
+            },
+            Err(_) => error(StatusCode::NOT_FOUND, "no such output"),
+        },
+        (Method::PUT, ["api", "outputs", index, "label"]) => {
+            set_label(state, api, req, index, false).await
+        },
+        (Method::PUT, ["api", "outputs", index, "lock"]) => {
+            set_lock(state, api, req, index).await
+        },
+        (Method::GET, ["api", "routes"]) => {
+            let state = state.lock().await;
+            json(StatusCode::OK, &routes(&state))
+        },
+        (Method::POST, ["api", "routes"]) => {
+            set_routes(state, api, req).await
+        },
+        (Method::POST, ["api", "take"]) => {
+            take(state, api, req).await
+        },
+        (Method::GET, ["api", "events"]) => {
+            events(state, api, req)
+        },
+        (Method::GET, _) => match panel::asset(req.uri().path()) {
+            Some(asset) => Response::builder()
+                .header("Content-Type", asset.content_type)
+                .body(Body::from(asset.body))
+                .expect("response parts are valid"),
+            None => error(StatusCode::NOT_FOUND, "not found"),
+        },
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    };
+
+    Ok(response)
+}
+
+fn inputs(state: &Shared) -> Vec<Input> {
+    let labels = state.video_hub.input_labels();
+
+    state.inputs.iter().enumerate()
+        .map(|(index, input)| Input {
+            index,
+            label: labels.get(index).cloned().unwrap_or_default(),
+            ndi_name: input.source.as_ref().map(|source| source.ndi_name().to_owned()),
+            ip: input.source.as_ref()
+                .map(|source| source.ip_address().to_owned())
+                .filter(|ip| !ip.is_empty()),
+            online: input.online,
+        })
+        .collect()
+}
+
+fn outputs(state: &Shared, api: SocketAddr) -> Vec<Output> {
+    let video_hub = &state.video_hub;
+
+    video_hub.output_labels().iter().enumerate()
+        .map(|(index, label)| Output {
+            index,
+            label: label.to_owned(),
+            ndi_name: state.output_names.get(index).cloned().unwrap_or_default(),
+            input: video_hub.routes().get(&index).cloned(),
+            lock: video_hub.lock_state(index, api),
+            take_mode: video_hub.needs_take(index),
+            pending: video_hub.pending_input(index),
+        })
+        .collect()
+}
+
+fn routes(state: &Shared) -> Vec<Crosspoint> {
+    state.video_hub.routes().iter()
+        .map(|(&output, &input)| Crosspoint { output, input })
+        .collect()
+}
+
+fn snapshot(state: &Shared, api: SocketAddr) -> Event {
+    let mut controllers: Vec<SocketAddr> = state.peers.keys().cloned().collect();
+    controllers.sort();
+
+    Event::Snapshot { inputs: inputs(state), outputs: outputs(state, api), controllers }
+}
+
+async fn set_label(state: Arc<Mutex<Shared>>, api: SocketAddr, req: Request<Body>, index: &str, input: bool) -> Response<Body> {
+    let index = match index.parse::<usize>() {
+        Ok(index) => index,
+        Err(_) => return error(StatusCode::NOT_FOUND, if input { "no such input" } else { "no such output" }),
+    };
+
+    let label: Label = match body(req).await {
+        Ok(label) => label,
+        Err(response) => return response,
+    };
+
+    // Labels go out on their own line of the Videohub protocol.
+    let text = label.label.trim();
+    if text.is_empty() || text.contains('\n') || text.contains('\r') {
+        return error(StatusCode::BAD_REQUEST, "labels must be a single line of text");
+    }
+
+    let labels = [(index, text.to_owned())];
+    let mut state = state.lock().await;
+    let applied = if input {
+        state.apply_input_labels(api, &labels).await
+    } else {
+        state.apply_output_labels(api, &labels).await
+    };
+
+    if !applied {
+        return error(StatusCode::NOT_FOUND, if input { "no such input" } else { "no such output" });
+    }
+
+    json(StatusCode::OK, &Label { label: text.to_owned() })
+}
+
+async fn set_lock(state: Arc<Mutex<Shared>>, api: SocketAddr, req: Request<Body>, index: &str) -> Response<Body> {
+    let index = match index.parse::<usize>() {
+        Ok(index) => index,
+        Err(_) => return error(StatusCode::NOT_FOUND, "no such output"),
+    };
+
+    let lock: Lock = match body(req).await {
+        Ok(lock) => lock,
+        Err(response) => return response,
+    };
+
+    if lock.lock == LockState::Locked {
+        return error(StatusCode::BAD_REQUEST, "lock must be owned, unlocked or force");
+    }
+
+    let mut state = state.lock().await;
+    if index >= state.outputs.len() {
+        return error(StatusCode::NOT_FOUND, "no such output");
+    }
+
+    if !state.apply_locks(api, &[(index, lock.lock)]).await {
+        return error(StatusCode::CONFLICT, "the output is locked by another client");
+    }
+
+    json(StatusCode::OK, &Lock { lock: state.video_hub.lock_state(index, api) })
+}
+
+async fn set_routes(state: Arc<Mutex<Shared>>, api: SocketAddr, req: Request<Body>) -> Response<Body> {
+    let crosspoints: Vec<Crosspoint> = match body(req).await {
+        Ok(crosspoints) => crosspoints,
+        Err(response) => return response,
+    };
+
+    if crosspoints.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "no routes given");
+    }
+
+    let routes: Vec<(usize, usize)> = crosspoints.iter().map(|c| (c.output, c.input)).collect();
+    let mut state = state.lock().await;
+
+    if !state.apply_routes(api, &routes).await {
+        return error(StatusCode::CONFLICT, "an output or input does not exist, or an output is locked by another client");
+    }
+
+    json(StatusCode::OK, &self::routes(&state))
+}
+
+async fn take(state: Arc<Mutex<Shared>>, api: SocketAddr, req: Request<Body>) -> Response<Body> {
+    let take: Take = match body::<Option<Take>>(req).await {
+        Ok(take) => take.unwrap_or_default(),
+        Err(response) => return response,
+    };
+
+    let mut state = state.lock().await;
+
+    if !state.take(api, &take.outputs).await {
+        return error(StatusCode::CONFLICT, "an output has nothing pending, or is locked by another client");
+    }
+
+    json(StatusCode::OK, &self::routes(&state))
+}
+
+/// Accepts a WebSocket for the event stream, which is carried on in the
+/// background once the upgrade response has been sent.
+fn events(state: Arc<Mutex<Shared>>, api: SocketAddr, req: Request<Body>) -> Response<Body> {
+    let mut handshake = Request::new(());
+    *handshake.method_mut() = req.method().clone();
+    *handshake.uri_mut() = req.uri().clone();
+    *handshake.version_mut() = req.version();
+    *handshake.headers_mut() = req.headers().clone();
+
+    let response = match create_response(&handshake) {
+        Ok(response) => response,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("not a WebSocket request: {}", e)),
+    };
+
+    tokio::spawn(async move {
+        match req.into_body().on_upgrade().await {
+            Ok(upgraded) => {
+                let ws = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                if let Err(e) = stream_events(state, api, ws).await {
+                    debug!("event stream ended: {}", e);
+                }
+            },
+            Err(e) => debug!("cannot upgrade to a WebSocket: {}", e),
+        }
+    });
+
+    let (parts, ()) = response.into_parts();
+    Response::from_parts(parts, Body::empty())
+}
+
+/// Sends a snapshot of the router followed by every change to it, until the
+/// client goes away.
+async fn stream_events(state: Arc<Mutex<Shared>>, api: SocketAddr, mut ws: WebSocketStream<Upgraded>) -> tungstenite::Result<()> {
+    // Subscribing while the state is locked means no change can fall between
+    // the snapshot and the first event.
+    let (current, mut events) = {
+        let state = state.lock().await;
+        (snapshot(&state, api), state.events.subscribe())
+    };
+    send(&mut ws, &current).await?;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => send(&mut ws, &Event::new(event, api)).await?,
+                // Events the client missed cannot be replayed on top of a
+                // newer state, so it starts again from a fresh snapshot.
+                Err(RecvError::Lagged(missed)) => {
+                    debug!("event stream fell {} events behind, resending the snapshot", missed);
+                    let current = {
+                        let state = state.lock().await;
+                        events = state.events.subscribe();
+                        snapshot(&state, api)
+                    };
+                    send(&mut ws, &current).await?;
+                },
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            // Nothing is expected from the client, pings are answered by
+            // tungstenite as they are read.
+            message = ws.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {},
+                Some(Err(e)) => return Err(e),
+            },
+        }
+    }
+}
+
+async fn send(ws: &mut WebSocketStream<Upgraded>, event: &Event) -> tungstenite::Result<()> {
+    let text = serde_json::to_string(event).expect("API types always serialize");
+    ws.send(Message::Text(text)).await
+}
+
+/// Reads a JSON request body, an empty body reads as `null`.
+async fn body<T: DeserializeOwned>(req: Request<Body>) -> Result<T, Response<Body>> {
+    let bytes = hyper::body::to_bytes(req.into_body()).await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+
+    let bytes: &[u8] = if bytes.iter().all(u8::is_ascii_whitespace) { b"null" } else { &bytes };
+
+    serde_json::from_slice(bytes)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &format!("invalid request body: {}", e)))
+}
+
+fn json<T: Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    let body = serde_json::to_vec(value).expect("API types always serialize");
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("response parts are valid")
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, &ApiError { error: message.to_owned() })
+}
@@ -0,0 +1,123 @@
+//! An NDI router controlled over the Blackmagic Videohub ethernet protocol.
+//!
+//! The crate is split into the NDI bindings (`ndi`), the Videohub protocol
+//! model (`videohub`), the router core shared by every connected controller
+//! (`shared`), the Videohub TCP server (`server`), the SW-P-08 server for
+//! Probel controllers (`swp08`), the Ember+ provider (`ember`) and the HTTP
+//! API (`http`), which streams the router's `events` to WebSocket clients and
+//! serves the web control panel (`panel`). The `ndi-router` binary is a thin
+//! wrapper that loads the config and wires these together.
+
+use tokio::sync::{Mutex};
+use log::{info};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod ndi;
+pub mod videohub;
+pub mod peer;
+pub mod events;
+pub mod shared;
+pub mod discovery;
+pub mod take;
+pub mod sources;
+pub mod persist;
+pub mod config;
+pub mod salvo;
+pub mod snapshots;
+pub mod server;
+pub mod swp08;
+pub mod ember;
+pub mod http;
+pub mod panel;
+
+use crate::videohub::{Identity, VideoHub};
+use crate::shared::{Shared};
+use crate::sources::{SourceMap};
+use crate::persist::{StateFile};
+use crate::snapshots::{Snapshots};
+use crate::config::{Config};
+use crate::ndi::{Backend};
+
+/// Builds the router's shared state on `backend`, restoring anything saved by
+/// a previous run, and starts discovering sources in the background.
+pub fn start(backend: &dyn Backend, config: &Config) -> Result<Arc<Mutex<Shared>>, Box<dyn Error>> {
+    let find_groups = Config::ndi_list(&config.find_groups);
+    let extra_ips = Config::ndi_list(&config.extra_ips);
+    let output_groups = Config::ndi_list(&config.output_groups);
+
+    if let Some(groups) = &find_groups {
+        info!("finding NDI sources in groups {}", groups);
+    }
+    if let Some(extra_ips) = &extra_ips {
+        info!("finding NDI sources on {}", extra_ips);
+    }
+
+    let find = backend.find(find_groups.as_ref().map(String::as_str), extra_ips.as_ref().map(String::as_str))
+        .ok_or("Cannot initialize NDI finder")?;
+
+    let source_map = SourceMap::load(&config.source_map)?;
+    let mut outputs  = vec![];
+    let mut video_hub = VideoHub::new(source_map.len(), config.outputs);
+    video_hub.set_identity(Identity {
+        protocol_version: config.protocol_version.clone(),
+        model_name: config.model_name.clone(),
+        unique_id: config.unique_id.clone().unwrap_or_default(),
+        friendly_name: config.friendly_name.clone(),
+    });
+
+    info!("Loaded {} known NDI sources from {}", source_map.len(), config.source_map.display());
+
+    for x in 0..config.outputs {
+        let name = config.output_name(x);
+        let route = backend.route(name.as_str(), output_groups.as_ref().map(String::as_str))
+            .ok_or("Cannot create NDI route")?;
+
+        outputs.push(route);
+    }
+
+    video_hub.set_take_mode(config.take_mode);
+    for output in &config.take_outputs {
+        video_hub.set_output_take_mode(*output, true);
+    }
+
+    let state_file = StateFile::new(&config.state_file);
+    let saved = state_file.load()?;
+    let snapshots = Snapshots::load(&config.snapshots)?;
+    let mut shared = Shared::new(video_hub, source_map, outputs, state_file, snapshots);
+    shared.salvos = config.salvos();
+    shared.output_names = (0..config.outputs).map(|x| config.output_name(x)).collect();
+
+    // Routes are only re-issued to NDI as each source is discovered, until
+    // then the restored routing table just reflects what should be on air.
+    if let Some(saved) = saved {
+        info!("Restoring router state from {}", config.state_file.display());
+        shared.video_hub.restore(saved);
+    }
+
+    // An ID in the config always wins, otherwise one is made up on the first
+    // run and saved so control software sees the same router every time.
+    let mut identity = shared.video_hub.identity().clone();
+    if let Some(unique_id) = &config.unique_id {
+        identity.unique_id = unique_id.to_owned();
+        shared.video_hub.set_identity(identity);
+    } else if identity.unique_id.is_empty() {
+        identity.unique_id = Identity::generate_unique_id();
+        info!("Generated unique ID {}", identity.unique_id);
+        shared.video_hub.set_identity(identity);
+        shared.save_state();
+    }
+
+    let state = Arc::new(Mutex::new(shared));
+
+    // Sources are picked up in the background so ones that appear after
+    // startup are routable too.
+    tokio::spawn(discovery::run(find, Arc::clone(&state)));
+
+    if config.take_timeout > 0 {
+        tokio::spawn(take::run(Arc::clone(&state), Duration::from_secs(config.take_timeout)));
+    }
+
+    Ok(state)
+}
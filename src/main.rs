@@ -1,212 +1,80 @@
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{Mutex};
-use tokio::stream::{Stream, StreamExt};
-use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
+use tokio::net::{TcpListener};
+use futures::FutureExt;
 use log4rs;
-use futures::SinkExt;
-use log::{error, info, debug};
-use std::{env, error::Error, mem};
+use log::{info};
+use std::{env, error::Error};
 use std::sync::Arc;
-use std::net::SocketAddr;
-use std::pin::Pin;
-use std::task::{Context, Poll};
 
-mod ndi;
-mod videohub;
-mod peer;
-mod shared;
-
-use crate::videohub::{VideoHub};
-use crate::peer::{Peer};
-use crate::shared::{Shared};
-use crate::ndi::{FindInstance, RouteInstance};
+use ndi_router::config::{self, Config};
+use ndi_router::ndi::{SdkBackend};
+use ndi_router::start;
+use ndi_router::server::{serve};
+use ndi_router::http;
+use ndi_router::swp08;
+use ndi_router::ember;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-const NUM_OUTPUTS: usize = 16;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    log4rs::init_file("config/log4rs.yaml", Default::default()).unwrap();
-
-    info!("starting ndi-router {}", VERSION);
-
-    if !ndi::initialize() {
-        panic!("Cannot initialize NDI libs");
-    }
-
-    let mut find = match FindInstance::builder().show_local_sources(true).build() {
-        None => panic!(Some("Cannot initialize NDI finder")),
-        Some(find) => find,
+    // The config path can be given as the only argument, otherwise the
+    // default is used if it exists.
+    let config = match env::args().nth(1) {
+        Some(path) => Config::load(path, true),
+        None => Config::load(config::DEFAULT_CONFIG_PATH, false),
     };
 
-    let new_sources = find.wait_for_sources(100);
-    let sources = find.get_current_sources();
-    let mut outputs  = vec![];
-    let mut inputs = vec![];
-    let mut video_hub = VideoHub::new(sources.len(), NUM_OUTPUTS);
-
-    info!("Found {} NDI sources", sources.len());
-
-    if new_sources {
-        let mut i : usize = 0;
-        for source in &sources {
-            let label = source.ndi_name().to_owned();
-            let ip = source.ip_address().to_owned();
-            debug!("Found source '{}' {} ({})",  i, label, ip);
-            video_hub.set_input_label(i, label);
-            inputs.push(source.to_owned());
-            i += 1;
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("ndi-router: {}", e);
+            std::process::exit(1);
         }
-    } else {
-        error!("No NDI sources found");
-        return Ok(())
-    }
-
-    for x in 0..NUM_OUTPUTS {
-        let name = format!("NDI output {}", x);
-        let route = match RouteInstance::builder(name.as_str()).build() {
-            None => panic!(Some("Cannot create NDI route")),
-            Some(find) => find,
-        };
+    };
 
-        outputs.push(route);
+    if let Err(e) = log4rs::init_file(&config.logging, Default::default()) {
+        eprintln!("ndi-router: cannot load logging config {}: {}", config.logging.display(), e);
+        std::process::exit(1);
     }
 
-    let state = Arc::new(Mutex::new(Shared::new(video_hub, inputs, outputs)));
-
-    // Parse the arguments, bind the TCP socket we'll be listening to, spin up
-    // our worker threads, and start shipping sockets to those worker threads.
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "127.0.0.1:9990".to_string());
-
-    let mut listener = TcpListener::bind(&addr).await?;
-
-    info!("server running on {}", addr);
+    info!("starting ndi-router {}", VERSION);
 
-    loop {
-        // Asynchronously wait for an inbound TcpStream.
-        let (stream, addr) = listener.accept().await?;
+    let backend = match SdkBackend::new() {
+        None => panic!("Cannot initialize NDI libs"),
+        Some(backend) => backend,
+    };
 
-        // Clone a handle to the `Shared` state for the new connection.
-        let state = Arc::clone(&state);
+    let state = start(&backend, &config)?;
 
-        // Spawn our handler to be run asynchronously.
-        tokio::spawn(async move {
-            if let Err(e) = process(state, stream, addr).await {
-                println!("an error occured; error = {:?}", e);
-            }
-        });
+    // Bind every TCP socket we'll be listening to before accepting on any of
+    // them, so a bad address stops the router at startup.
+    let mut listeners = Vec::with_capacity(config.listen.len());
+    for addr in &config.listen {
+        listeners.push(TcpListener::bind(addr).await?);
+        info!("server running on {}", addr);
     }
-}
 
-#[derive(Debug)]
-pub enum Message {
-    /// A message that should be broadcasted to others.
-    Received(Vec<String>),
-
-    Broadcast(String),
-}
-
-// Peer implements `Stream` in a way that polls both the `Rx`, and `Framed` types.
-// A message is produced whenever an event is ready until the `Framed` stream returns `None`.
-impl Stream for Peer {
-    type Item = Result<Message, LinesCodecError>;
-
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-
-        if let Poll::Ready(Some(v)) = Pin::new(&mut self.rx).poll_next(cx) {
-            return Poll::Ready(Some(Ok(Message::Broadcast(v))));
-        }
-
-        // Secondly poll the `Framed` stream.
-        let result: Option<_> = futures::ready!(Pin::new(&mut self.lines).poll_next(cx));
-
-        Poll::Ready(match result {
-            // We've received a message we should broadcast to others.
-            Some(Ok(message)) => {
-                if message == "" {
-                    Some(Ok(Message::Received(mem::replace(&mut self.buf, vec![]))))
-                } else {
-                    self.buf.push(message);
-                    Some(Ok(Message::Received(vec!["None".to_owned()])))
-                }
-            },
-
-            // An error occured.
-            Some(Err(e)) => Some(Err(e)),
-
-            // The stream has been exhausted.
-            None => None,
-        })
+    let mut http_listeners = Vec::with_capacity(config.http_listen.len());
+    for addr in &config.http_listen {
+        http_listeners.push(TcpListener::bind(addr).await?);
     }
-}
-
-/// Process an individual chat client
-async fn process(
-    state: Arc<Mutex<Shared>>,
-    stream: TcpStream,
-    addr: SocketAddr,
-) -> Result<(), Box<dyn Error>> {
-    info!("New videohub controller connected: {}", addr);
-    
-    let mut lines = Framed::new(stream, LinesCodec::new());
-    let video_hub = state.lock().await.video_hub.clone();
-    lines.send(video_hub.inital_status_dump()).await?;
 
-    // Register our peer with state which internally sets up some channels.
-    let mut peer = Peer::new(state.clone(), lines).await?;
-
-    // Process incoming messages until our stream is exhausted by a disconnect.
-    while let Some(result) = peer.next().await {
-        match result {
-            // A message was received from the current user, we should
-            // broadcast this message to the other users.
-            Ok(Message::Received(msg)) => {
-                let command = msg.first().unwrap().as_str();
-                match command {
-                    "PING:" => {
-                        debug!("sending ACK to {}", peer.addr);
-                        peer.lines.send("ACK\n".to_owned()).await?
-                    },
-                    "VIDEO OUTPUT ROUTING:" => {
-                        let mut split = msg[1].split_whitespace();
-                        let mut state = state.lock().await;
-                        let route = state.outputs.get(split.next().unwrap().parse::<usize>().unwrap());
-                        let source = state.inputs.get(split.next().unwrap().parse::<usize>().unwrap());
-                        
-                        route.unwrap().clear();
-                        route.unwrap().change(source.unwrap());
-                        let update = format!("{}\n{}\n\n", command, msg[1]);
-                        state.broadcast(addr, &update).await;
-                        peer.lines.send("ACK\n".to_owned()).await?
-                    },
-                    "VIDEO OUTPUT LOCKS:" => {
-                        println!("{}", msg[1]);
-                        peer.lines.send("ACK\n".to_owned()).await?
-                    }
-                    _ => (),
-                }
-            },
-            Ok(Message::Broadcast(msg)) => {
-                peer.lines.send(msg).await?;
-            }
-            Err(e) => {
-                println!(
-                    "an error occured while processing messages error = {:?}",
-                    e
-                );
-            }
-        }
+    let mut swp08_listeners = Vec::with_capacity(config.swp08_listen.len());
+    for addr in &config.swp08_listen {
+        swp08_listeners.push(TcpListener::bind(addr).await?);
     }
 
-    // If this section is reached it means that the client was disconnected!
-    // Let's let everyone still connected know about it.
-    {
-        info!("Client {} Disconnected", addr);
-        let mut state = state.lock().await;
-        state.peers.remove(&addr);
+    let mut ember_listeners = Vec::with_capacity(config.ember_listen.len());
+    for addr in &config.ember_listen {
+        ember_listeners.push(TcpListener::bind(addr).await?);
     }
 
+    let servers = listeners.into_iter()
+        .map(|listener| serve(listener, Arc::clone(&state)).boxed())
+        .chain(http_listeners.into_iter().map(|listener| http::serve(listener, Arc::clone(&state)).boxed()))
+        .chain(swp08_listeners.into_iter().map(|listener| swp08::server::serve(listener, Arc::clone(&state)).boxed()))
+        .chain(ember_listeners.into_iter().map(|listener| ember::server::serve(listener, Arc::clone(&state)).boxed()));
+    futures::future::try_join_all(servers).await?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,92 @@
+use crate::ndi::{self, FindInstance, RouteInstance, Source};
+
+/// The discovery and routing operations the router needs from NDI.
+///
+/// `SdkBackend` drives the real NDI SDK, `SimulatedBackend` stands in for it
+/// with an in-memory network so the router can run without the SDK.
+pub trait Backend: Send + Sync {
+    /// Starts finding sources, optionally restricted to comma separated NDI
+    /// groups and also querying the comma separated `extra_ips`.
+    fn find(&self, groups: Option<&str>, extra_ips: Option<&str>) -> Option<Box<dyn Finder>>;
+
+    /// Publishes a routed output called `ndi_name`, optionally in the comma
+    /// separated NDI groups.
+    fn route(&self, ndi_name: &str, groups: Option<&str>) -> Option<Box<dyn Route>>;
+}
+
+/// Watches the network for sources.
+pub trait Finder: Send {
+    /// Waits up to `timeout_in_ms` for the set of sources to change, returning
+    /// true if it did.
+    fn wait_for_sources(&mut self, timeout_in_ms: u32) -> bool;
+
+    /// The sources visible right now.
+    fn current_sources(&mut self) -> Vec<Source<'static>>;
+}
+
+/// An output that can be switched between sources.
+pub trait Route: Send + Sync {
+    fn change(&self, source: &Source);
+
+    fn clear(&self);
+}
+
+/// The NDI SDK, reached through FFI.
+pub struct SdkBackend;
+
+impl SdkBackend {
+    /// Initializes the NDI libs, `None` if they cannot be loaded.
+    pub fn new() -> Option<SdkBackend> {
+        if ndi::initialize() {
+            Some(SdkBackend)
+        } else {
+            None
+        }
+    }
+}
+
+impl Backend for SdkBackend {
+    fn find(&self, groups: Option<&str>, extra_ips: Option<&str>) -> Option<Box<dyn Finder>> {
+        let mut builder = FindInstance::builder().show_local_sources(true);
+
+        if let Some(groups) = groups {
+            builder = builder.groups(groups);
+        }
+
+        if let Some(extra_ips) = extra_ips {
+            builder = builder.extra_ips(extra_ips);
+        }
+
+        builder.build().map(|find| Box::new(find) as Box<dyn Finder>)
+    }
+
+    fn route(&self, ndi_name: &str, groups: Option<&str>) -> Option<Box<dyn Route>> {
+        let mut builder = RouteInstance::builder(ndi_name);
+
+        if let Some(groups) = groups {
+            builder = builder.groups(groups);
+        }
+
+        builder.build().map(|route| Box::new(route) as Box<dyn Route>)
+    }
+}
+
+impl Finder for FindInstance {
+    fn wait_for_sources(&mut self, timeout_in_ms: u32) -> bool {
+        FindInstance::wait_for_sources(self, timeout_in_ms)
+    }
+
+    fn current_sources(&mut self) -> Vec<Source<'static>> {
+        self.get_current_sources().iter().map(|s| s.to_owned()).collect()
+    }
+}
+
+impl Route for RouteInstance {
+    fn change(&self, source: &Source) {
+        RouteInstance::change(self, source)
+    }
+
+    fn clear(&self) {
+        RouteInstance::clear(self)
+    }
+}
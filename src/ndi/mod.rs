@@ -1,6 +1,11 @@
 mod ndisys;
+pub mod backend;
+pub mod simulated;
 use crate::ndi::ndisys::*;
 
+pub use crate::ndi::backend::{Backend, Finder, Route, SdkBackend};
+pub use crate::ndi::simulated::{RouteCall, SimulatedBackend};
+
 use std::ffi;
 use std::mem;
 use std::ptr;
@@ -57,6 +62,23 @@ impl<'a> FindBuilder<'a> {
         }
     }
 
+    /// Only find sources in the given comma separated NDI groups.
+    pub fn groups(self, groups: &'a str) -> Self {
+        Self {
+            groups: Some(groups),
+            ..self
+        }
+    }
+
+    /// Comma separated IPs of machines to query for sources, in addition to
+    /// those found by mDNS, for finding sources on other subnets.
+    pub fn extra_ips(self, extra_ips: &'a str) -> Self {
+        Self {
+            extra_ips: Some(extra_ips),
+            ..self
+        }
+    }
+
     pub fn build(self) -> Option<FindInstance> {
         let groups = self.groups.map(|s| ffi::CString::new(s).unwrap());
         let extra_ips = self.extra_ips.map(|s| ffi::CString::new(s).unwrap());
@@ -153,6 +175,22 @@ unsafe impl<'a> Send for Source<'a> {}
 unsafe impl<'a> Sync for Source<'a> {}
 
 impl<'a> Source<'a> {
+    /// Creates an owned source from its NDI name and address, for sources the
+    /// finder has not (yet) reported.
+    pub fn new(ndi_name: &str, ip_address: &str) -> Source<'a> {
+        let ndi_name = ffi::CString::new(ndi_name).unwrap();
+        let ip_address = ffi::CString::new(ip_address).unwrap();
+
+        Source::Owned(
+            NDIlib_source_t {
+                p_ndi_name: ndi_name.as_ptr(),
+                p_ip_address: ip_address.as_ptr(),
+            },
+            ndi_name,
+            ip_address,
+        )
+    }
+
     pub fn ndi_name(&self) -> &str {
         unsafe {
             let ptr = match *self {
@@ -245,6 +283,14 @@ pub struct RouteBuilder<'a> {
 }
 
 impl<'a> RouteBuilder<'a> {
+    /// Publish the route into the given comma separated NDI groups.
+    pub fn groups(self, groups: &'a str) -> Self {
+        Self {
+            groups: Some(groups),
+            ..self
+        }
+    }
+
     pub fn build(self) -> Option<RouteInstance> {
         unsafe {
             let ndi_name = ffi::CString::new(self.ndi_name).unwrap();
@@ -0,0 +1,159 @@
+use std::sync::{Arc, Mutex};
+
+use log::info;
+
+use crate::ndi::Source;
+use crate::ndi::backend::{Backend, Finder, Route};
+
+/// A call made on a simulated route, recorded in the order it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RouteCall {
+    Change { output: String, source: String },
+    Clear { output: String },
+}
+
+#[derive(Default)]
+struct Network {
+    /// Visible sources as `(ndi_name, ip_address)`.
+    sources: Vec<(String, String)>,
+
+    /// Bumped on every change to `sources` so finders can tell something
+    /// happened since they last looked.
+    generation: u64,
+
+    /// Groups and extra IPs each finder was created with.
+    finds: Vec<(Option<String>, Option<String>)>,
+
+    /// Published outputs as `(ndi_name, groups)`.
+    outputs: Vec<(String, Option<String>)>,
+    calls: Vec<RouteCall>,
+}
+
+/// An in-memory NDI network. Sources are made to appear and disappear by the
+/// caller and every route change is recorded instead of hitting the wire.
+///
+/// Clones share the same network, so a test can keep one handle to script
+/// the network while the router uses another.
+#[derive(Clone, Default)]
+pub struct SimulatedBackend {
+    network: Arc<Mutex<Network>>,
+}
+
+impl SimulatedBackend {
+    pub fn new() -> SimulatedBackend {
+        SimulatedBackend::default()
+    }
+
+    /// Makes a source visible, replacing the address of one with the same
+    /// name.
+    pub fn add_source(&self, ndi_name: &str, ip_address: &str) {
+        let mut network = self.network.lock().unwrap();
+        network.sources.retain(|(name, _)| name != ndi_name);
+        network.sources.push((ndi_name.to_owned(), ip_address.to_owned()));
+        network.generation += 1;
+    }
+
+    pub fn remove_source(&self, ndi_name: &str) {
+        let mut network = self.network.lock().unwrap();
+        network.sources.retain(|(name, _)| name != ndi_name);
+        network.generation += 1;
+    }
+
+    /// The groups and extra IPs of every finder created, in creation order.
+    pub fn finds(&self) -> Vec<(Option<String>, Option<String>)> {
+        self.network.lock().unwrap().finds.clone()
+    }
+
+    /// NDI names of the outputs that have been published and the groups
+    /// they were published in, in creation order.
+    pub fn outputs(&self) -> Vec<(String, Option<String>)> {
+        self.network.lock().unwrap().outputs.clone()
+    }
+
+    /// Every route call made so far.
+    pub fn route_calls(&self) -> Vec<RouteCall> {
+        self.network.lock().unwrap().calls.clone()
+    }
+
+    /// Every route call made since the last time this was called.
+    pub fn take_route_calls(&self) -> Vec<RouteCall> {
+        std::mem::replace(&mut self.network.lock().unwrap().calls, Vec::new())
+    }
+
+    /// The source `output` was last changed to, `None` if it has never been
+    /// routed or was cleared since.
+    pub fn routed_source(&self, output: &str) -> Option<String> {
+        let network = self.network.lock().unwrap();
+
+        for call in network.calls.iter().rev() {
+            match call {
+                RouteCall::Change { output: o, source } if o == output => return Some(source.clone()),
+                RouteCall::Clear { output: o } if o == output => return None,
+                _ => (),
+            }
+        }
+
+        None
+    }
+}
+
+impl Backend for SimulatedBackend {
+    fn find(&self, groups: Option<&str>, extra_ips: Option<&str>) -> Option<Box<dyn Finder>> {
+        self.network.lock().unwrap().finds.push((groups.map(str::to_owned), extra_ips.map(str::to_owned)));
+
+        Some(Box::new(SimulatedFinder {
+            network: Arc::clone(&self.network),
+            generation: 0,
+        }))
+    }
+
+    fn route(&self, ndi_name: &str, groups: Option<&str>) -> Option<Box<dyn Route>> {
+        self.network.lock().unwrap().outputs.push((ndi_name.to_owned(), groups.map(str::to_owned)));
+
+        Some(Box::new(SimulatedRoute {
+            network: Arc::clone(&self.network),
+            ndi_name: ndi_name.to_owned(),
+        }))
+    }
+}
+
+struct SimulatedFinder {
+    network: Arc<Mutex<Network>>,
+    generation: u64,
+}
+
+impl Finder for SimulatedFinder {
+    fn wait_for_sources(&mut self, _timeout_in_ms: u32) -> bool {
+        let generation = self.network.lock().unwrap().generation;
+        let changed = generation != self.generation;
+        self.generation = generation;
+        changed
+    }
+
+    fn current_sources(&mut self) -> Vec<Source<'static>> {
+        self.network.lock().unwrap().sources.iter()
+            .map(|(ndi_name, ip_address)| Source::new(ndi_name, ip_address))
+            .collect()
+    }
+}
+
+struct SimulatedRoute {
+    network: Arc<Mutex<Network>>,
+    ndi_name: String,
+}
+
+impl Route for SimulatedRoute {
+    fn change(&self, source: &Source) {
+        info!("routing {:?} to {:?}", source.ndi_name(), self.ndi_name);
+        self.network.lock().unwrap().calls.push(RouteCall::Change {
+            output: self.ndi_name.clone(),
+            source: source.ndi_name().to_owned(),
+        });
+    }
+
+    fn clear(&self) {
+        self.network.lock().unwrap().calls.push(RouteCall::Clear {
+            output: self.ndi_name.clone(),
+        });
+    }
+}
@@ -0,0 +1,32 @@
+/// A file of the web control panel.
+pub struct Asset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub body: &'static str,
+}
+
+/// The web control panel, built into the binary so the router can still be
+/// copied around as a single file. The panel only talks to the HTTP API.
+const ASSETS: &[Asset] = &[
+    Asset {
+        path: "/",
+        content_type: "text/html; charset=utf-8",
+        body: include_str!("index.html"),
+    },
+    Asset {
+        path: "/panel.js",
+        content_type: "application/javascript; charset=utf-8",
+        body: include_str!("panel.js"),
+    },
+    Asset {
+        path: "/panel.css",
+        content_type: "text/css; charset=utf-8",
+        body: include_str!("panel.css"),
+    },
+];
+
+/// Returns the panel file served at `path`, if there is one.
+pub fn asset(path: &str) -> Option<&'static Asset> {
+    let path = if path == "/index.html" { "/" } else { path };
+    ASSETS.iter().find(|asset| asset.path == path)
+}
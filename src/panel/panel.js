@@ -0,0 +1,292 @@
+// Web control panel for the router. Keeps a copy of the router's state from
+// the event stream and makes changes through the HTTP API, so it shows
+// whatever any other controller does as it happens.
+'use strict';
+
+const RECONNECT_DELAY = 2000;
+
+const router = {
+  inputs: [],
+  outputs: [],
+  controllers: [],
+};
+
+let selected = null;
+let connected = false;
+
+function $(id) {
+  return document.getElementById(id);
+}
+
+function element(tag, className, text) {
+  const el = document.createElement(tag);
+  if (className) {
+    el.className = className;
+  }
+  if (text !== undefined) {
+    el.textContent = text;
+  }
+  return el;
+}
+
+function inputLabel(index) {
+  const input = router.inputs[index];
+  return input ? input.label : '';
+}
+
+// Event stream
+
+function connect() {
+  const scheme = location.protocol === 'https:' ? 'wss' : 'ws';
+  const ws = new WebSocket(`${scheme}://${location.host}/api/events`);
+
+  ws.onopen = () => {
+    connected = true;
+    render();
+  };
+
+  ws.onmessage = (message) => {
+    apply(JSON.parse(message.data));
+    render();
+  };
+
+  ws.onclose = () => {
+    connected = false;
+    render();
+    setTimeout(connect, RECONNECT_DELAY);
+  };
+}
+
+function apply(event) {
+  switch (event.type) {
+    case 'snapshot':
+      router.inputs = event.inputs;
+      router.outputs = event.outputs;
+      router.controllers = event.controllers;
+      break;
+    case 'routes':
+      for (const { output, input } of event.routes) {
+        if (router.outputs[output]) {
+          router.outputs[output].input = input;
+        }
+      }
+      break;
+    case 'pending':
+      for (const output of router.outputs) {
+        output.pending = null;
+      }
+      for (const { output, input } of event.routes) {
+        if (router.outputs[output]) {
+          router.outputs[output].pending = input;
+        }
+      }
+      break;
+    case 'take_modes':
+      for (const { output, take_mode } of event.outputs) {
+        if (router.outputs[output]) {
+          router.outputs[output].take_mode = take_mode;
+        }
+      }
+      break;
+    case 'input_labels':
+      for (const { index, label } of event.labels) {
+        if (router.inputs[index]) {
+          router.inputs[index].label = label;
+        }
+      }
+      break;
+    case 'output_labels':
+      for (const { index, label } of event.labels) {
+        if (router.outputs[index]) {
+          router.outputs[index].label = label;
+        }
+      }
+      break;
+    case 'locks':
+      for (const { output, lock } of event.locks) {
+        if (router.outputs[output]) {
+          router.outputs[output].lock = lock;
+        }
+      }
+      break;
+    case 'source': {
+      // A new source gets the next input, its label follows on its own.
+      const { index, ndi_name, ip, online } = event;
+      if (!router.inputs[index]) {
+        router.inputs[index] = { index, label: ndi_name };
+      }
+      Object.assign(router.inputs[index], { ndi_name, ip, online });
+      break;
+    }
+    case 'controller_connected':
+      router.controllers.push(event.addr);
+      break;
+    case 'controller_disconnected':
+      router.controllers = router.controllers.filter((addr) => addr !== event.addr);
+      break;
+  }
+}
+
+// Changes
+
+async function request(method, path, body) {
+  try {
+    const res = await fetch(path, {
+      method,
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify(body),
+    });
+
+    if (res.ok) {
+      showError(null);
+    } else {
+      const error = await res.json();
+      showError(error.error);
+    }
+  } catch (e) {
+    showError(`Cannot reach the router: ${e.message}`);
+  }
+}
+
+function route(output, input) {
+  return request('POST', '/api/routes', [{ output, input }]);
+}
+
+function take() {
+  return request('POST', '/api/take', {});
+}
+
+function toggleLock(output) {
+  // A lock held by another controller can only be forced off.
+  const lock = { owned: 'unlocked', locked: 'force', unlocked: 'owned' }[router.outputs[output].lock];
+  return request('PUT', `/api/outputs/${output}/lock`, { lock });
+}
+
+function showError(message) {
+  $('error').hidden = !message;
+  $('error').textContent = message || '';
+}
+
+// Rendering
+
+function render() {
+  const status = $('status');
+  status.className = connected ? '' : 'offline';
+  status.textContent = connected
+    ? `${router.controllers.length} Videohub controller${router.controllers.length === 1 ? '' : 's'}`
+    : 'Disconnected';
+
+  $('take').disabled = !router.outputs.some((output) => output.pending !== null);
+
+  renderGrid();
+  renderDestinations();
+}
+
+function renderGrid() {
+  const head = element('tr');
+  head.appendChild(element('th'));
+  for (const input of router.inputs) {
+    const th = element('th', input.online ? '' : 'offline');
+    th.appendChild(element('span', '', input.label));
+    th.title = input.ndi_name || '';
+    head.appendChild(th);
+  }
+  $('grid').querySelector('thead').replaceChildren(head);
+
+  const rows = router.outputs.map((output) => {
+    const tr = element('tr', output.lock === 'locked' ? 'locked' : '');
+    tr.appendChild(element('th', '', output.label));
+
+    for (const input of router.inputs) {
+      let className = '';
+      if (output.input === input.index) {
+        className = 'routed';
+      } else if (output.pending === input.index) {
+        className = 'pending';
+      }
+
+      const td = element('td', className);
+      td.title = `${input.label} → ${output.label}`;
+      if (output.lock !== 'locked') {
+        td.onclick = () => route(output.index, input.index);
+      }
+      tr.appendChild(td);
+    }
+
+    return tr;
+  });
+  $('grid').querySelector('tbody').replaceChildren(...rows);
+}
+
+function renderDestinations() {
+  const outputs = router.outputs.map((output) => {
+    const button = element('button', output.index === selected ? 'selected' : '', output.label);
+    button.type = 'button';
+
+    let source = output.input === null ? 'No source' : inputLabel(output.input);
+    if (output.pending !== null) {
+      source += ` (${inputLabel(output.pending)} pending)`;
+    }
+    if (output.lock !== 'unlocked') {
+      source += ` – ${output.lock}`;
+    }
+    button.appendChild(element('small', '', source));
+    button.onclick = () => {
+      selected = output.index;
+      render();
+    };
+
+    const li = element('li');
+    li.appendChild(button);
+    return li;
+  });
+  $('outputs').replaceChildren(...outputs);
+
+  const output = router.outputs[selected];
+  $('sources-title').textContent = output ? `Sources for ${output.label}` : 'Sources';
+
+  const lock = $('lock');
+  lock.disabled = !output;
+  lock.textContent = output && output.lock === 'owned' ? 'Unlock'
+    : output && output.lock === 'locked' ? 'Force unlock'
+    : 'Lock';
+  lock.onclick = () => output && toggleLock(output.index);
+
+  const inputs = router.inputs.map((input) => {
+    let className = input.online ? '' : 'offline';
+    if (output && output.input === input.index) {
+      className += ' routed';
+    } else if (output && output.pending === input.index) {
+      className += ' pending';
+    }
+
+    const button = element('button', className.trim(), input.label);
+    button.type = 'button';
+    button.appendChild(element('small', '', input.online ? input.ndi_name : 'Offline'));
+    button.disabled = !output || output.lock === 'locked' || !input.ndi_name;
+    button.onclick = () => route(output.index, input.index);
+
+    const li = element('li');
+    li.appendChild(button);
+    return li;
+  });
+  $('inputs').replaceChildren(...inputs);
+}
+
+// Setup
+
+for (const button of document.querySelectorAll('nav button')) {
+  button.onclick = () => {
+    for (const other of document.querySelectorAll('nav button')) {
+      other.classList.toggle('selected', other === button);
+    }
+    for (const view of document.querySelectorAll('.view')) {
+      view.hidden = view.id !== button.dataset.view;
+    }
+  };
+}
+
+$('take').onclick = take;
+
+render();
+connect();
@@ -1,28 +1,27 @@
 use tokio::net::{TcpStream};
 use tokio::sync::{mpsc, Mutex};
-use tokio_util::codec::{Framed, LinesCodec};
+use tokio_util::codec::{Framed};
 use std::io;
 use std::sync::Arc;
 
 use std::net::SocketAddr;
 
 use crate::shared::{Shared};
+use crate::videohub::{Block, VideohubCodec};
 
 /// Shorthand for the transmit half of the message channel.
-pub type Tx = mpsc::UnboundedSender<String>;
+pub type Tx = mpsc::UnboundedSender<Block>;
 
 /// Shorthand for the receive half of the message channel.
-pub type Rx = mpsc::UnboundedReceiver<String>;
+pub type Rx = mpsc::UnboundedReceiver<Block>;
 
 pub struct Peer {
-    /// The TCP socket wrapped with the `Lines` codec, defined below.
+    /// The TCP socket wrapped with the `VideohubCodec`.
     ///
-    /// This handles sending and receiving data on the socket. When using
-    /// `Lines`, we can work at the line level instead of having to manage the
-    /// raw byte operations.
-    pub lines: Framed<TcpStream, LinesCodec>,
-
-    pub buf: Vec<String>,
+    /// This handles sending and receiving data on the socket. With the codec
+    /// we work with whole protocol blocks instead of having to manage lines
+    /// or raw bytes.
+    pub blocks: Framed<TcpStream, VideohubCodec>,
 
     pub addr: SocketAddr,
 
@@ -38,19 +37,17 @@ impl Peer {
     /// Create a new instance of `Peer`.
     pub async fn new(
         state: Arc<Mutex<Shared>>,
-        lines: Framed<TcpStream, LinesCodec>,
+        blocks: Framed<TcpStream, VideohubCodec>,
     ) -> io::Result<Peer> {
         // Get the client socket address
-        let addr = lines.get_ref().peer_addr()?;
+        let addr = blocks.get_ref().peer_addr()?;
 
         // Create a channel for this peer
         let (tx, rx) = mpsc::unbounded_channel();
 
-        let buf = Vec::new();
-
         // Add an entry for this `Peer` in the shared state map.
-        state.lock().await.peers.insert(addr, tx);
+        state.lock().await.connect(addr, tx);
 
-        Ok(Peer { lines, buf, rx, addr })
+        Ok(Peer { blocks, rx, addr })
     }
 }
\ No newline at end of file
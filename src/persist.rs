@@ -0,0 +1,113 @@
+use tokio::sync::watch;
+use tokio::task;
+use log::{error};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::videohub::{VideoHub};
+
+/// A serialized state waiting to be written, numbered in the order it was
+/// saved.
+type Save = Option<(u64, Vec<u8>)>;
+
+/// The on-disk copy of the router state: labels, routing and locks, saved
+/// after every change so a restart comes back the way it was left.
+///
+/// Saves are written by a background task, so nothing waits on the disk while
+/// holding the router state. States saved while a write is in progress are
+/// coalesced and only the latest is written.
+pub struct StateFile {
+    path: PathBuf,
+    saves: watch::Sender<Save>,
+    /// The number of the last save written, or that failed to be.
+    written: watch::Receiver<u64>,
+    queued: AtomicU64,
+}
+
+impl StateFile {
+    /// Opens the state file at `path` and starts its writer task, so must be
+    /// called inside the runtime.
+    pub fn new<P: AsRef<Path>>(path: P) -> StateFile {
+        let path = path.as_ref().to_path_buf();
+        let (saves, pending) = watch::channel(None);
+        let (done, written) = watch::channel(0);
+
+        tokio::spawn(write_saves(path.clone(), pending, done));
+
+        StateFile { path, saves, written, queued: AtomicU64::new(0) }
+    }
+
+    /// Reads the last saved state, `None` if nothing has been saved yet.
+    pub fn load(&self) -> io::Result<Option<VideoHub>> {
+        match fs::read(&self.path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queues `video_hub` to be written in the background. Errors writing
+    /// it are logged by the writer task.
+    pub fn save(&self, video_hub: &VideoHub) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(video_hub)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let number = self.queued.fetch_add(1, Ordering::SeqCst) + 1;
+
+        self.saves.broadcast(Some((number, contents)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "state file writer has stopped"))
+    }
+
+    /// Waits until everything saved so far has been written, or has failed
+    /// to be.
+    pub async fn flush(&self) {
+        let queued = self.queued.load(Ordering::SeqCst);
+        let mut written = self.written.clone();
+
+        while *written.borrow() < queued {
+            if written.recv().await.is_none() {
+                break;
+            }
+        }
+    }
+}
+
+/// Writes each state saved to `pending` to `path`, off the async threads,
+/// reporting the number of each one done to `done`.
+async fn write_saves(path: PathBuf, mut pending: watch::Receiver<Save>, done: watch::Sender<u64>) {
+    while let Some(save) = pending.recv().await {
+        let (number, contents) = match save {
+            Some(save) => save,
+            None => continue,
+        };
+
+        let target = path.clone();
+        match task::spawn_blocking(move || write_atomic(&target, &contents)).await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => error!("Cannot save router state: {}", e),
+            Err(e) => error!("Cannot save router state: {}", e),
+        }
+
+        let _ = done.broadcast(number);
+    }
+}
+
+/// Replaces the file at `path` with `contents` without ever leaving a half
+/// written file behind. The data is written and synced to a temporary file
+/// next to the target, which is then renamed over it.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,46 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// An output or input in a salvo, either by index or by NDI name.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Port {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Port::Index(i) => write!(f, "{}", i),
+            Port::Name(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+/// One crosspoint of a salvo as written in the config. Outputs are named by
+/// their NDI output name and inputs by their NDI source name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SalvoRoute {
+    pub output: Port,
+    pub input: Port,
+}
+
+/// A named set of crosspoints that are switched together.
+///
+/// Outputs are fixed when the router starts so are kept by index. Inputs are
+/// looked up when the salvo is fired, as a source named in it may only be
+/// found on the network later.
+#[derive(Clone, Debug)]
+pub struct Salvo {
+    pub name: String,
+    pub routes: Vec<(usize, Port)>,
+}
+
+/// A crosspoint of a salvo that was not switched, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Refused {
+    pub output: usize,
+    pub reason: String,
+}
@@ -0,0 +1,204 @@
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex};
+use tokio::stream::{Stream, StreamExt};
+use tokio_util::codec::{Framed};
+use futures::SinkExt;
+use log::{info, debug};
+use std::{error::Error, io};
+use std::sync::Arc;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::videohub::{Block, VideohubCodec};
+use crate::salvo::{Refused};
+use crate::peer::{Peer};
+use crate::shared::{Shared};
+
+/// Accepts Videohub controllers on `listener` until it fails.
+pub async fn serve(mut listener: TcpListener, state: Arc<Mutex<Shared>>) -> io::Result<()> {
+    loop {
+        // Asynchronously wait for an inbound TcpStream.
+        let (stream, addr) = listener.accept().await?;
+
+        // Clone a handle to the `Shared` state for the new connection.
+        let state = Arc::clone(&state);
+
+        // Spawn our handler to be run asynchronously.
+        tokio::spawn(async move {
+            if let Err(e) = process(state, stream, addr).await {
+                println!("an error occured; error = {:?}", e);
+            }
+        });
+    }
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// A block received from the client.
+    Received(Block),
+
+    /// A block from the router to pass on to the client.
+    Broadcast(Block),
+}
+
+// Peer implements `Stream` in a way that polls both the `Rx`, and `Framed` types.
+// A message is produced whenever an event is ready until the `Framed` stream returns `None`.
+impl Stream for Peer {
+    type Item = Result<Message, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+
+        if let Poll::Ready(Some(v)) = Pin::new(&mut self.rx).poll_next(cx) {
+            return Poll::Ready(Some(Ok(Message::Broadcast(v))));
+        }
+
+        // Secondly poll the `Framed` stream.
+        let result: Option<_> = futures::ready!(Pin::new(&mut self.blocks).poll_next(cx));
+
+        Poll::Ready(match result {
+            // We've received a block from the client.
+            Some(Ok(block)) => Some(Ok(Message::Received(block))),
+
+            // An error occured.
+            Some(Err(e)) => Some(Err(e)),
+
+            // The stream has been exhausted.
+            None => None,
+        })
+    }
+}
+
+/// Acknowledges a salvo or snapshot recall, followed by the crosspoints that
+/// were refused if there were any.
+async fn send_refused(peer: &mut Peer, refused: Vec<Refused>) -> io::Result<()> {
+    peer.blocks.send(Block::Ack).await?;
+
+    if !refused.is_empty() {
+        let refused = refused.into_iter().map(|r| (r.output, r.reason)).collect();
+        peer.blocks.send(Block::RoutesRefused(refused)).await?;
+    }
+
+    Ok(())
+}
+
+/// Process an individual chat client
+pub async fn process(
+    state: Arc<Mutex<Shared>>,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> Result<(), Box<dyn Error>> {
+    info!("New videohub controller connected: {}", addr);
+    
+    let mut blocks = Framed::new(stream, VideohubCodec::new());
+    let dump = state.lock().await.video_hub.inital_status_dump(addr);
+    for block in dump {
+        blocks.send(block).await?;
+    }
+
+    // Register our peer with state which internally sets up some channels.
+    let mut peer = Peer::new(state.clone(), blocks).await?;
+
+    // Process incoming messages until our stream is exhausted by a disconnect.
+    while let Some(result) = peer.next().await {
+        match result {
+            // A block was received from the current user, apply it and let
+            // the user know whether it was accepted.
+            Ok(Message::Received(block)) => {
+                let header = block.header().to_owned();
+
+                // A block with no body asks for the current state of just
+                // that block, which is sent straight after the ACK.
+                if block.is_request() {
+                    let status = state.lock().await.status(&header, addr);
+                    if let Some(status) = status {
+                        debug!("sending {} to {}", header, peer.addr);
+                        peer.blocks.send(Block::Ack).await?;
+                        peer.blocks.send(status).await?;
+                        continue;
+                    }
+                }
+
+                let accepted = match block {
+                    Block::Ping => true,
+                    Block::Routing(routes) if !routes.is_empty() => {
+                        state.lock().await.apply_routes(addr, &routes).await
+                    },
+                    Block::Locks(locks) if !locks.is_empty() => {
+                        state.lock().await.apply_locks(addr, &locks).await
+                    },
+                    Block::InputLabels(labels) if !labels.is_empty() => {
+                        state.lock().await.apply_input_labels(addr, &labels).await
+                    },
+                    Block::OutputLabels(labels) if !labels.is_empty() => {
+                        state.lock().await.apply_output_labels(addr, &labels).await
+                    },
+                    Block::Configuration(settings) if !settings.is_empty() => {
+                        state.lock().await.apply_configuration(addr, &settings).await
+                    },
+                    Block::TakeMode(take_modes) if !take_modes.is_empty() => {
+                        state.lock().await.apply_take_modes(addr, &take_modes).await
+                    },
+                    Block::Take(outputs) => {
+                        state.lock().await.take(addr, &outputs).await
+                    },
+                    Block::FireSalvo(name) => {
+                        let refused = state.lock().await.fire_salvo(addr, &name).await;
+                        match refused {
+                            Some(refused) => {
+                                send_refused(&mut peer, refused).await?;
+                                continue;
+                            },
+                            None => false,
+                        }
+                    },
+                    Block::SaveSnapshot(name) => {
+                        state.lock().await.save_snapshot(addr, &name).await
+                    },
+                    Block::RecallSnapshot(name) => {
+                        let refused = state.lock().await.recall_snapshot(addr, &name).await;
+                        match refused {
+                            Some(refused) => {
+                                send_refused(&mut peer, refused).await?;
+                                continue;
+                            },
+                            None => false,
+                        }
+                    },
+                    Block::DeleteSnapshot(name) => {
+                        state.lock().await.delete_snapshot(addr, &name).await
+                    },
+                    // Acknowledgements need no reply of their own.
+                    Block::Ack | Block::Nak => continue,
+                    _ => false,
+                };
+
+                if accepted {
+                    debug!("sending ACK to {}", peer.addr);
+                    peer.blocks.send(Block::Ack).await?
+                } else {
+                    debug!("rejected {} from {}, sending NAK", header, peer.addr);
+                    peer.blocks.send(Block::Nak).await?
+                }
+            },
+            Ok(Message::Broadcast(block)) => {
+                peer.blocks.send(block).await?;
+            }
+            Err(e) => {
+                println!(
+                    "an error occured while processing messages error = {:?}",
+                    e
+                );
+            }
+        }
+    }
+
+    // If this section is reached it means that the client was disconnected!
+    // Let's let everyone still connected know about it.
+    {
+        info!("Client {} Disconnected", addr);
+        state.lock().await.disconnect(addr).await;
+    }
+
+    Ok(())
+}
\ No newline at end of file
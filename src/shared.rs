@@ -1,9 +1,24 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::time::Duration;
+use log::{debug, error, info};
 
-use crate::videohub::{VideoHub};
+use crate::videohub::{Block, LockState, VideoHub};
+use crate::videohub::codec::{SALVOS, SNAPSHOTS};
 use crate::peer::{Tx};
-use crate::ndi::{Source, RouteInstance};
+use crate::events::{self, Event, EventTx};
+use crate::ndi::{Source, Route};
+use crate::sources::{SourceMap};
+use crate::persist::{StateFile};
+use crate::salvo::{Port, Refused, Salvo};
+use crate::snapshots::{Snapshots};
+
+/// An input slot. The slot keeps its index while the NDI source it refers to
+/// comes and goes from the network, a free slot has no source at all.
+pub struct Input {
+    pub source: Option<Source<'static>>,
+    pub online: bool,
+}
 
 /// Data that is shared between all peers in the chat server.
 ///
@@ -14,28 +29,634 @@ use crate::ndi::{Source, RouteInstance};
 pub struct Shared {
     pub peers: HashMap<SocketAddr, Tx>,
     pub video_hub: VideoHub,
-    pub inputs: Vec<Source<'static>>,
-    pub outputs: Vec<RouteInstance>,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Box<dyn Route>>,
+    pub source_map: SourceMap,
+    pub state_file: StateFile,
+    pub salvos: Vec<Salvo>,
+    pub snapshots: Snapshots,
+    /// The NDI name each output is published under.
+    pub output_names: Vec<String>,
+    /// Every change made to the router, for watchers other than Videohub
+    /// controllers to subscribe to.
+    pub events: EventTx,
 }
 
 impl Shared {
     /// Create a new, empty, instance of `Shared`.
-    pub fn new(video_hub: VideoHub, inputs: Vec<Source<'static>>, outputs: Vec<RouteInstance>) -> Self {
+    ///
+    /// Every source in `source_map` gets an offline placeholder input so the
+    /// numbering matches previous runs before discovery has found anything.
+    pub fn new(
+        mut video_hub: VideoHub,
+        source_map: SourceMap,
+        outputs: Vec<Box<dyn Route>>,
+        state_file: StateFile,
+        snapshots: Snapshots,
+    ) -> Self {
+        let mut inputs = Vec::with_capacity(source_map.len());
+
+        for (i, slot) in source_map.slots().iter().enumerate() {
+            let source = slot.as_ref().map(|ndi_name| {
+                video_hub.set_input_label(i, ndi_name.to_owned());
+                Source::new(ndi_name, "")
+            });
+
+            inputs.push(Input { source, online: false });
+        }
+
         Shared {
             peers: HashMap::new(),
             video_hub,
             outputs,
-            inputs
+            inputs,
+            source_map,
+            state_file,
+            salvos: Vec::new(),
+            snapshots,
+            output_names: Vec::new(),
+            events: events::channel(),
+        }
+    }
+
+    /// Saves the current labels, routing and locks to the state file, which
+    /// writes them in the background.
+    pub fn save_state(&self) {
+        if let Err(e) = self.state_file.save(&self.video_hub) {
+            error!("Cannot save router state: {}", e);
+        }
+    }
+
+    /// Switches `output` to carry `input`, updating the NDI route and the
+    /// videohub routing table together so status dumps match what is on air.
+    ///
+    /// Returns false if either index does not exist or the input is a free
+    /// slot.
+    pub fn route(&mut self, output: usize, input: usize) -> bool {
+        let source = self.inputs.get(input).and_then(|input| input.source.as_ref());
+        let (route, source) = match (self.outputs.get(output), source) {
+            (Some(route), Some(source)) => (route, source),
+            _ => return false,
+        };
+
+        route.clear();
+        route.change(source);
+        self.video_hub.set_route(output, input);
+
+        true
+    }
+
+    /// Reconciles the input slots with the sources currently visible on the
+    /// network. Known sources go back to the slot in the source map, new ones
+    /// are assigned a free slot and sources that have gone away are left in
+    /// place as offline placeholders.
+    ///
+    /// Connected controllers are sent the new device info and labels if any
+    /// inputs were added.
+    pub async fn update_sources(&mut self, sources: Vec<Source<'static>>) {
+        let mut added = Vec::new();
+        let was_online: Vec<bool> = self.inputs.iter().map(|input| input.online).collect();
+
+        for input in self.inputs.iter_mut() {
+            input.online = false;
+        }
+
+        for source in sources {
+            let i = match self.source_map.slot(source.ndi_name()) {
+                Some(i) => i,
+                None => match self.source_map.assign(source.ndi_name()) {
+                    Ok(i) => {
+                        info!("Found new NDI source '{}' ({}) as input {}", source.ndi_name(), source.ip_address(), i);
+                        added.push(i);
+                        i
+                    },
+                    Err(e) => {
+                        error!("Cannot save source map, ignoring '{}': {}", source.ndi_name(), e);
+                        continue;
+                    },
+                },
+            };
+
+            while self.inputs.len() <= i {
+                let index = self.video_hub.add_input(format!("NDI Input {}", self.inputs.len()));
+                self.inputs.push(Input { source: None, online: false });
+                debug!("Added input {}", index);
+            }
+
+            if added.contains(&i) {
+                self.video_hub.set_input_label(i, source.ndi_name().to_owned());
+            } else if !self.inputs[i].online {
+                info!("NDI source '{}' is online as input {}", source.ndi_name(), i);
+            }
+
+            self.inputs[i] = Input { source: Some(source), online: true };
+        }
+
+        for (i, input) in self.inputs.iter().enumerate() {
+            if let (Some(source), false) = (&input.source, input.online) {
+                debug!("NDI source '{}' on input {} is offline", source.ndi_name(), i);
+            }
+
+            if let Some(source) = &input.source {
+                if input.online != was_online.get(i).cloned().unwrap_or(false) {
+                    self.publish(Event::Source {
+                        input: i,
+                        ndi_name: source.ndi_name().to_owned(),
+                        ip: source.ip_address().to_owned(),
+                        online: input.online,
+                    });
+                }
+            }
+        }
+
+        // Outputs restored from the state file, or left routed to a source
+        // that went away, are routed again now the source can be reached.
+        for i in 0..self.inputs.len() {
+            if self.inputs[i].online && !was_online.get(i).cloned().unwrap_or(false) {
+                for output in self.video_hub.outputs_for_input(i) {
+                    debug!("Re-routing input {} to output {}", i, output);
+                    self.route(output, i);
+                }
+            }
+        }
+
+        if !added.is_empty() {
+            self.save_state();
+
+            let device_info = self.video_hub.device_info();
+            let labels = self.video_hub.input_label_update(&added);
+            self.broadcast_all(&device_info).await;
+            self.broadcast_all(&labels).await;
+        }
+    }
+
+    /// Applies a block of `(output, input)` crosspoints requested by `peer`.
+    /// Crosspoints on outputs in take mode are held as pending until taken.
+    ///
+    /// The block is rejected as a whole, without touching any route, if an
+    /// output or input does not exist or an output is locked by another peer.
+    pub async fn apply_routes(&mut self, peer: SocketAddr, routes: &[(usize, usize)]) -> bool {
+        let valid = routes.iter().all(|&(output, input)| {
+            output < self.outputs.len()
+                && self.inputs.get(input).map_or(false, |input| input.source.is_some())
+                && !self.video_hub.is_locked_by_other(output, peer)
+        });
+
+        if !valid {
+            return false;
+        }
+
+        let (routed, staged) = self.switch(routes);
+
+        if !routed.is_empty() {
+            let update = self.video_hub.route_update(&routed);
+            self.broadcast(peer, &update).await;
+        }
+
+        // The sender is told too, its ACK does not say the route is on hold.
+        if staged {
+            let pending = self.video_hub.list_pending();
+            self.broadcast_all(&pending).await;
+        }
+
+        true
+    }
+
+    /// Routes every crosspoint, which must already have been checked, or
+    /// holds it as pending if its output is in take mode. Returns the outputs
+    /// that were routed and whether anything was held.
+    fn switch(&mut self, routes: &[(usize, usize)]) -> (Vec<usize>, bool) {
+        let mut routed = Vec::new();
+        let mut staged = false;
+
+        for &(output, input) in routes {
+            if self.video_hub.needs_take(output) {
+                debug!("Holding input {} on output {} until taken", input, output);
+                self.video_hub.stage_route(output, input);
+                staged = true;
+            } else {
+                self.route(output, input);
+                routed.push(output);
+            }
+        }
+
+        if !routed.is_empty() {
+            self.save_state();
+        }
+
+        (routed, staged)
+    }
+
+    /// Fires the salvo called `name` on behalf of `peer`, switching all of its
+    /// crosspoints at once with a single routing update to every peer.
+    ///
+    /// Crosspoints on outputs locked by another peer, or to inputs that
+    /// cannot be found, are left alone and returned. Returns `None` if there
+    /// is no such salvo.
+    pub async fn fire_salvo(&mut self, peer: SocketAddr, name: &str) -> Option<Vec<Refused>> {
+        let salvo = self.salvos.iter().find(|salvo| salvo.name == name)?.clone();
+        let mut routes = Vec::new();
+        let mut refused = Vec::new();
+
+        for (output, input) in &salvo.routes {
+            let output = *output;
+            let index = match input {
+                Port::Index(i) => Some(*i),
+                Port::Name(name) => self.source_map.slot(name),
+            };
+            let index = index.filter(|&i| self.inputs.get(i).map_or(false, |input| input.source.is_some()));
+
+            if self.video_hub.is_locked_by_other(output, peer) {
+                refused.push(Refused { output, reason: "locked".to_owned() });
+            } else if let Some(index) = index {
+                routes.push((output, index));
+            } else {
+                refused.push(Refused { output, reason: format!("no input {}", input) });
+            }
+        }
+
+        info!("Firing salvo '{}' for {}, {} routes, {} refused", salvo.name, peer, routes.len(), refused.len());
+        self.switch_all(&routes).await;
+
+        Some(refused)
+    }
+
+    /// Switches `routes`, which must already have been checked, and sends a
+    /// single update to every peer as nobody has seen the routes yet, the
+    /// peer that asked for them included.
+    async fn switch_all(&mut self, routes: &[(usize, usize)]) {
+        let (routed, staged) = self.switch(routes);
+
+        if !routed.is_empty() {
+            let update = self.video_hub.route_update(&routed);
+            self.broadcast_all(&update).await;
+        }
+
+        if staged {
+            let pending = self.video_hub.list_pending();
+            self.broadcast_all(&pending).await;
+        }
+    }
+
+    /// Saves the current routing table as the snapshot `name`, replacing any
+    /// snapshot already called that, and sends the new list to other peers.
+    pub async fn save_snapshot(&mut self, peer: SocketAddr, name: &str) -> bool {
+        let routes = self.video_hub.routes().clone();
+
+        if let Err(e) = self.snapshots.insert(name, routes) {
+            error!("Cannot save snapshot '{}': {}", name, e);
+            return false;
         }
+
+        info!("Saved routing snapshot '{}' for {}", name, peer);
+        let list = self.list_snapshots();
+        self.broadcast(peer, &list).await;
+
+        true
     }
 
-    /// Send a `LineCodec` encoded message to every peer, except
-    /// for the sender.
-    pub async fn broadcast(&mut self, sender: SocketAddr, message: &str) {
+    /// Recalls the snapshot `name` on behalf of `peer`. Only outputs routed
+    /// differently to the snapshot are switched, all at once with a single
+    /// routing update to every peer.
+    ///
+    /// Outputs locked by another peer, or that the snapshot routes to an input
+    /// or output that no longer exists, are left alone and returned. Returns
+    /// `None` if there is no such snapshot.
+    pub async fn recall_snapshot(&mut self, peer: SocketAddr, name: &str) -> Option<Vec<Refused>> {
+        let snapshot = self.snapshots.get(name)?.clone();
+        let mut routes = Vec::new();
+        let mut refused = Vec::new();
+
+        for (output, input) in snapshot {
+            if self.video_hub.routes().get(&output) == Some(&input) {
+                continue;
+            }
+
+            if output >= self.outputs.len() {
+                refused.push(Refused { output, reason: "no such output".to_owned() });
+            } else if self.video_hub.is_locked_by_other(output, peer) {
+                refused.push(Refused { output, reason: "locked".to_owned() });
+            } else if !self.inputs.get(input).map_or(false, |input| input.source.is_some()) {
+                refused.push(Refused { output, reason: format!("no input {}", input) });
+            } else {
+                routes.push((output, input));
+            }
+        }
+
+        info!("Recalling snapshot '{}' for {}, {} changed routes, {} refused", name, peer, routes.len(), refused.len());
+        self.switch_all(&routes).await;
+
+        Some(refused)
+    }
+
+    /// Deletes the snapshot `name`, returning false if there is no such
+    /// snapshot or it could not be removed from disk.
+    pub async fn delete_snapshot(&mut self, peer: SocketAddr, name: &str) -> bool {
+        match self.snapshots.remove(name) {
+            Ok(true) => {
+                info!("Deleted routing snapshot '{}' for {}", name, peer);
+                let list = self.list_snapshots();
+                self.broadcast(peer, &list).await;
+                true
+            },
+            Ok(false) => false,
+            Err(e) => {
+                error!("Cannot delete snapshot '{}': {}", name, e);
+                false
+            },
+        }
+    }
+
+    pub fn list_snapshots(&self) -> Block {
+        Block::Snapshots(self.snapshots.names().into_iter().enumerate().collect())
+    }
+
+    /// Applies the crosspoints pending on `outputs`, or on every output when
+    /// empty, on behalf of `peer`.
+    ///
+    /// Rejected without taking anything if a named output has nothing pending
+    /// or any output is locked by another peer.
+    pub async fn take(&mut self, peer: SocketAddr, outputs: &[usize]) -> bool {
+        let outputs = if outputs.is_empty() {
+            self.video_hub.pending_outputs()
+        } else {
+            outputs.to_vec()
+        };
+
+        let valid = outputs.iter().all(|&output| {
+            self.video_hub.is_pending(output) && !self.video_hub.is_locked_by_other(output, peer)
+        });
+
+        if !valid {
+            return false;
+        }
+
+        for &output in &outputs {
+            if let Some(input) = self.video_hub.take_pending(output) {
+                info!("Taking input {} on output {} for {}", input, output, peer);
+                self.route(output, input);
+            }
+        }
+
+        if !outputs.is_empty() {
+            // Nobody has seen these routes yet, the taker included.
+            let update = self.video_hub.route_update(&outputs);
+            let pending = self.video_hub.list_pending();
+            self.broadcast_all(&update).await;
+            self.broadcast_all(&pending).await;
+            self.save_state();
+        }
+
+        true
+    }
+
+    /// Drops crosspoints that have been pending longer than `timeout`, letting
+    /// every peer know.
+    pub async fn expire_pending(&mut self, timeout: Duration) {
+        let expired = self.video_hub.expire_pending(timeout);
+
+        if !expired.is_empty() {
+            info!("Dropped pending routes on outputs {:?} that were not taken", expired);
+            let pending = self.video_hub.list_pending();
+            self.broadcast_all(&pending).await;
+        }
+    }
+
+    /// Turns take mode on or off for the outputs in a `TAKE MODE:` block from
+    /// `peer`. Pending crosspoints on outputs leaving take mode are dropped.
+    pub async fn apply_take_modes(&mut self, peer: SocketAddr, take_modes: &[(usize, bool)]) -> bool {
+        let valid = take_modes.iter().all(|&(output, _)| {
+            output < self.outputs.len() && !self.video_hub.is_locked_by_other(output, peer)
+        });
+
+        if !valid {
+            return false;
+        }
+
+        let mut dropped = false;
+        for &(output, take) in take_modes {
+            dropped |= self.video_hub.set_output_take_mode(output, take);
+        }
+
+        let outputs: Vec<usize> = take_modes.iter().map(|&(output, _)| output).collect();
+        let update = self.video_hub.take_mode_update(&outputs);
+        self.broadcast(peer, &update).await;
+
+        if dropped {
+            let pending = self.video_hub.list_pending();
+            self.broadcast_all(&pending).await;
+        }
+
+        true
+    }
+
+    /// Applies a block of `(output, state)` lock requests from `peer`,
+    /// rejecting the whole block if any request is not allowed.
+    pub async fn apply_locks(&mut self, peer: SocketAddr, locks: &[(usize, LockState)]) -> bool {
+        let valid = locks.iter().all(|&(output, state)| {
+            output < self.outputs.len() && self.video_hub.lock_allowed(output, peer, state)
+        });
+
+        if !valid {
+            return false;
+        }
+
+        for &(output, state) in locks {
+            self.video_hub.set_lock(output, peer, state);
+        }
+
+        let outputs: Vec<usize> = locks.iter().map(|&(output, _)| output).collect();
+        self.broadcast_locks(peer, &outputs).await;
+
+        self.save_state();
+
+        true
+    }
+
+    /// Renames inputs as requested by `peer` in an `INPUT LABELS:` block and
+    /// passes the new names on to every other peer.
+    pub async fn apply_input_labels(&mut self, peer: SocketAddr, labels: &[(usize, String)]) -> bool {
+        if labels.iter().any(|(input, _)| *input >= self.inputs.len()) {
+            return false;
+        }
+
+        for (input, label) in labels {
+            self.video_hub.set_input_label(*input, label.to_owned());
+        }
+
+        let inputs: Vec<usize> = labels.iter().map(|(input, _)| *input).collect();
+        let update = self.video_hub.input_label_update(&inputs);
+        self.broadcast(peer, &update).await;
+
+        self.save_state();
+
+        true
+    }
+
+    /// Renames outputs as requested by `peer` in an `OUTPUT LABELS:` block and
+    /// passes the new names on to every other peer.
+    pub async fn apply_output_labels(&mut self, peer: SocketAddr, labels: &[(usize, String)]) -> bool {
+        if labels.iter().any(|(output, _)| *output >= self.outputs.len()) {
+            return false;
+        }
+
+        for (output, label) in labels {
+            self.video_hub.set_output_label(*output, label.to_owned());
+        }
+
+        let outputs: Vec<usize> = labels.iter().map(|(output, _)| *output).collect();
+        let update = self.video_hub.output_label_update(&outputs);
+        self.broadcast(peer, &update).await;
+
+        self.save_state();
+
+        true
+    }
+
+    /// Applies the settings in a `CONFIGURATION:` block from `peer`, which
+    /// can change the friendly name and turn take mode on or off. Unknown
+    /// settings reject the whole block.
+    pub async fn apply_configuration(&mut self, peer: SocketAddr, settings: &[(String, String)]) -> bool {
+        let valid = settings.iter().all(|(key, value)| match key.as_str() {
+            "Friendly name" => !value.is_empty(),
+            "Take Mode" => value == "true" || value == "false",
+            _ => false,
+        });
+
+        if !valid {
+            return false;
+        }
+
+        let mut renamed = false;
+        let mut take_mode_set = false;
+        let mut dropped = false;
+
+        for (key, value) in settings {
+            match key.as_str() {
+                "Friendly name" => {
+                    info!("Friendly name changed to '{}' by {}", value, peer);
+                    self.video_hub.set_friendly_name(value.to_owned());
+                    renamed = true;
+                },
+                "Take Mode" => {
+                    info!("Take mode turned {} by {}", if value == "true" { "on" } else { "off" }, peer);
+                    dropped |= self.video_hub.set_take_mode(value == "true");
+                    take_mode_set = true;
+                },
+                _ => {},
+            }
+        }
+
+        if renamed {
+            let device_info = self.video_hub.device_info();
+            self.broadcast(peer, &device_info).await;
+        }
+
+        if take_mode_set {
+            let configuration = self.video_hub.configuration();
+            self.broadcast(peer, &configuration).await;
+        }
+
+        if dropped {
+            let pending = self.video_hub.list_pending();
+            self.broadcast_all(&pending).await;
+        }
+
+        self.save_state();
+
+        true
+    }
+
+    /// The current state of the block with `header` as seen by `peer`, in
+    /// reply to a status request.
+    pub fn status(&self, header: &str, peer: SocketAddr) -> Option<Block> {
+        if header == SALVOS {
+            return Some(Block::Salvos(self.salvos.iter().map(|salvo| salvo.name.clone()).enumerate().collect()));
+        }
+
+        if header == SNAPSHOTS {
+            return Some(self.list_snapshots());
+        }
+
+        self.video_hub.status(header, peer)
+    }
+
+    /// Registers a newly connected peer to be sent broadcasts on `tx`.
+    pub fn connect(&mut self, peer: SocketAddr, tx: Tx) {
+        self.peers.insert(peer, tx);
+        self.publish(Event::ControllerConnected(peer));
+    }
+
+    /// Forgets a disconnected peer, releasing any locks it held.
+    pub async fn disconnect(&mut self, peer: SocketAddr) {
+        self.peers.remove(&peer);
+        self.publish(Event::ControllerDisconnected(peer));
+
+        let released = self.video_hub.release_locks(peer);
+        if !released.is_empty() {
+            debug!("releasing {} locks held by {}", released.len(), peer);
+            self.broadcast_locks(peer, &released).await;
+            self.save_state();
+        }
+    }
+
+    /// Send a block to every peer, except for the sender.
+    pub async fn broadcast(&mut self, sender: SocketAddr, block: &Block) {
+        self.publish_block(block);
+
+        for peer in self.peers.iter_mut() {
+            if *peer.0 != sender {
+                let _ = peer.1.send(block.clone());
+            }
+        }
+    }
+
+    /// Send a block to every peer.
+    pub async fn broadcast_all(&mut self, block: &Block) {
+        self.publish_block(block);
+
+        for peer in self.peers.iter_mut() {
+            let _ = peer.1.send(block.clone());
+        }
+    }
+
+    /// Send the lock state of `outputs` to every peer, except for the sender.
+    /// Each peer gets its own view of the locks as ownership is relative.
+    pub async fn broadcast_locks(&mut self, sender: SocketAddr, outputs: &[usize]) {
+        let owners = outputs.iter().map(|&output| (output, self.video_hub.lock_owner(output))).collect();
+        self.publish(Event::Locks(owners));
+
         for peer in self.peers.iter_mut() {
             if *peer.0 != sender {
-                let _ = peer.1.send(message.into());
+                let _ = peer.1.send(self.video_hub.lock_update(outputs, *peer.0));
             }
         }
     }
+
+    /// Publishes the change a block sent to peers describes as an event.
+    /// Blocks that do not change what watchers are shown are left out.
+    fn publish_block(&self, block: &Block) {
+        let event = match block {
+            Block::Routing(routes) => Event::Routes(routes.clone()),
+            Block::PendingRouting(pending) => Event::Pending(pending.clone()),
+            Block::InputLabels(labels) => Event::InputLabels(labels.clone()),
+            Block::OutputLabels(labels) => Event::OutputLabels(labels.clone()),
+            // Whether an output holds routes depends on take mode being on at
+            // all too, so watchers are given the result rather than the block.
+            Block::TakeMode(take_modes) => Event::TakeModes(take_modes.iter()
+                .map(|&(output, _)| (output, self.video_hub.needs_take(output)))
+                .collect()),
+            Block::Configuration(_) => Event::TakeModes((0..self.outputs.len())
+                .map(|output| (output, self.video_hub.needs_take(output)))
+                .collect()),
+            _ => return,
+        };
+
+        self.publish(event);
+    }
+
+    /// Sends `event` to every subscriber, there may not be any.
+    fn publish(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
 }
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::persist;
+
+/// Named copies of the whole routing table, kept on disk so they can be
+/// recalled after a restart.
+///
+/// The file is a JSON object of snapshot names, each mapping outputs to the
+/// input they were routed to.
+pub struct Snapshots {
+    path: PathBuf,
+    snapshots: BTreeMap<String, BTreeMap<usize, usize>>,
+}
+
+impl Snapshots {
+    /// Loads the snapshots from `path`, starting with none if the file does
+    /// not exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshots> {
+        let path = path.as_ref().to_path_buf();
+
+        let snapshots = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Snapshots { path, snapshots })
+    }
+
+    /// Snapshot names in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        self.snapshots.keys().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BTreeMap<usize, usize>> {
+        self.snapshots.get(name)
+    }
+
+    /// Stores `routes` as the snapshot `name`, replacing any snapshot already
+    /// called that, and saves the file. Nothing changes if it cannot be saved.
+    pub fn insert(&mut self, name: &str, routes: BTreeMap<usize, usize>) -> io::Result<()> {
+        let mut snapshots = self.snapshots.clone();
+        snapshots.insert(name.to_owned(), routes);
+
+        self.save(&snapshots)?;
+        self.snapshots = snapshots;
+        Ok(())
+    }
+
+    /// Removes the snapshot `name` and saves the file, returning false if
+    /// there was no such snapshot. Nothing changes if it cannot be saved.
+    pub fn remove(&mut self, name: &str) -> io::Result<bool> {
+        let mut snapshots = self.snapshots.clone();
+        if snapshots.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&snapshots)?;
+        self.snapshots = snapshots;
+        Ok(true)
+    }
+
+    fn save(&self, snapshots: &BTreeMap<String, BTreeMap<usize, usize>>) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(snapshots)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        persist::write_atomic(&self.path, &contents)
+    }
+}
@@ -0,0 +1,86 @@
+use log::{error};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::persist;
+
+/// Assignment of NDI source names to input slots, kept on disk so a source
+/// lands on the same input every time the router starts.
+///
+/// The file is a JSON array indexed by input number. A `null` entry is a free
+/// slot that the next new source will take.
+pub struct SourceMap {
+    path: PathBuf,
+    slots: Vec<Option<String>>,
+}
+
+impl SourceMap {
+    /// Loads the map from `path`, starting with an empty map if the file does
+    /// not exist yet.
+    ///
+    /// Names NDI could never have given, because they contain a NUL, are
+    /// dropped leaving their slot free.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SourceMap> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut slots: Vec<Option<String>> = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        for (i, slot) in slots.iter_mut().enumerate() {
+            if let Some(ndi_name) = slot.take() {
+                if ndi_name.contains('\0') {
+                    error!("Ignoring NDI source {:?} for input {} in {}, names cannot contain NUL", ndi_name, i, path.display());
+                } else {
+                    *slot = Some(ndi_name);
+                }
+            }
+        }
+
+        Ok(SourceMap { path, slots })
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn slots(&self) -> &[Option<String>] {
+        &self.slots
+    }
+
+    /// Returns the input slot assigned to the NDI source `ndi_name`.
+    pub fn slot(&self, ndi_name: &str) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.as_ref().map(|s| s.as_str()) == Some(ndi_name))
+    }
+
+    /// Assigns `ndi_name` the first free slot, or a new one at the end, and
+    /// saves the map. The map is left as it was if it cannot be saved, so
+    /// what is in memory always matches the file.
+    pub fn assign(&mut self, ndi_name: &str) -> io::Result<usize> {
+        let mut slots = self.slots.clone();
+        let slot = match slots.iter().position(|slot| slot.is_none()) {
+            Some(slot) => {
+                slots[slot] = Some(ndi_name.to_owned());
+                slot
+            },
+            None => {
+                slots.push(Some(ndi_name.to_owned()));
+                slots.len() - 1
+            },
+        };
+
+        self.save(&slots)?;
+        self.slots = slots;
+        Ok(slot)
+    }
+
+    fn save(&self, slots: &[Option<String>]) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(slots)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        persist::write_atomic(&self.path, &contents)
+    }
+}
@@ -0,0 +1,172 @@
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use std::io;
+
+use crate::swp08::Message;
+
+pub const DLE: u8 = 0x10;
+pub const STX: u8 = 0x02;
+pub const ETX: u8 = 0x03;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+
+/// Longest message data accepted, well over anything the protocol sends.
+/// Anything longer is line noise or a broken controller.
+const MAX_MESSAGE_LENGTH: usize = 1024;
+
+/// What is sent on a SW-P-08 link. Every message is acknowledged with an
+/// `Ack`, or a `Nak` if it arrived damaged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Message(Message),
+    Ack,
+    Nak,
+    /// A message whose byte count or checksum did not match, which should
+    /// be answered with a `Nak` so the sender tries again.
+    Corrupt,
+}
+
+/// Frames a byte stream into SW-P-08 messages.
+///
+/// A message is sent as `DLE STX`, its data, a byte count and a checksum,
+/// then `DLE ETX`. A `DLE` anywhere between the two is sent twice.
+pub struct Swp08Codec;
+
+impl Swp08Codec {
+    pub fn new() -> Swp08Codec {
+        Swp08Codec
+    }
+}
+
+impl Default for Swp08Codec {
+    fn default() -> Self {
+        Swp08Codec::new()
+    }
+}
+
+/// The two's complement of the sum of the data and byte count.
+pub fn checksum(data: &[u8]) -> u8 {
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    0u8.wrapping_sub(sum)
+}
+
+impl Decoder for Swp08Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, io::Error> {
+        loop {
+            // Anything before a DLE is noise between messages.
+            match src.iter().position(|&byte| byte == DLE) {
+                Some(start) => src.advance(start),
+                None => {
+                    src.clear();
+                    return Ok(None);
+                },
+            }
+
+            if src.len() < 2 {
+                return Ok(None);
+            }
+
+            match src[1] {
+                ACK => {
+                    src.advance(2);
+                    return Ok(Some(Frame::Ack));
+                },
+                NAK => {
+                    src.advance(2);
+                    return Ok(Some(Frame::Nak));
+                },
+                STX => {},
+                _ => {
+                    src.advance(1);
+                    continue;
+                },
+            }
+
+            let mut data = Vec::new();
+            let mut i = 2;
+
+            loop {
+                if data.len() > MAX_MESSAGE_LENGTH {
+                    src.advance(i);
+                    return Ok(Some(Frame::Corrupt));
+                }
+
+                match (src.get(i), src.get(i + 1)) {
+                    (Some(&DLE), Some(&DLE)) => {
+                        data.push(DLE);
+                        i += 2;
+                    },
+                    (Some(&DLE), Some(&ETX)) => {
+                        src.advance(i + 2);
+                        return Ok(Some(unpack(data)));
+                    },
+                    // The start of another message, or an ACK or NAK, cuts
+                    // this one short.
+                    (Some(&DLE), Some(_)) => {
+                        src.advance(i);
+                        return Ok(Some(Frame::Corrupt));
+                    },
+                    (Some(&DLE), None) | (None, _) => return Ok(None),
+                    (Some(&byte), _) => {
+                        data.push(byte);
+                        i += 1;
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Checks and strips the byte count and checksum from the end of a message.
+fn unpack(mut data: Vec<u8>) -> Frame {
+    if data.len() < 3 || checksum(&data) != 0 {
+        return Frame::Corrupt;
+    }
+
+    data.pop();
+    let count = data.pop().unwrap_or_default();
+
+    if count as usize != data.len() {
+        return Frame::Corrupt;
+    }
+
+    Frame::Message(Message::parse(&data))
+}
+
+impl Encoder for Swp08Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let message = match frame {
+            Frame::Message(message) => message,
+            Frame::Ack => {
+                dst.put_slice(&[DLE, ACK]);
+                return Ok(());
+            },
+            Frame::Nak | Frame::Corrupt => {
+                dst.put_slice(&[DLE, NAK]);
+                return Ok(());
+            },
+        };
+
+        let mut data = message.to_bytes();
+        data.push(data.len() as u8);
+        data.push(checksum(&data));
+
+        dst.reserve(data.len() * 2 + 4);
+        dst.put_slice(&[DLE, STX]);
+        for byte in data {
+            if byte == DLE {
+                dst.put_u8(DLE);
+            }
+            dst.put_u8(byte);
+        }
+        dst.put_slice(&[DLE, ETX]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+pub mod codec;
+pub mod server;
+
+pub use crate::swp08::codec::{Frame, Swp08Codec};
+
+pub const INTERROGATE: u8 = 0x01;
+pub const CONNECT: u8 = 0x02;
+pub const TALLY: u8 = 0x03;
+pub const CONNECTED: u8 = 0x04;
+pub const TALLY_DUMP_REQUEST: u8 = 0x15;
+pub const TALLY_DUMP_WORD: u8 = 0x17;
+pub const SOURCE_NAMES_REQUEST: u8 = 0x64;
+pub const DEST_NAMES_REQUEST: u8 = 0x66;
+pub const SOURCE_NAMES: u8 = 0x6A;
+pub const DEST_NAMES: u8 = 0x6B;
+
+/// Highest destination or source number the general messages can carry,
+/// three multiplier bits on top of seven.
+pub const MAX_PORT: usize = 1023;
+
+/// Most tallies a word tally dump message carries.
+pub const MAX_TALLIES: usize = 64;
+
+/// How long names are in a name request and its responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameLength {
+    Four,
+    Eight,
+    Twelve,
+}
+
+impl NameLength {
+    pub fn parse(byte: u8) -> Option<NameLength> {
+        match byte {
+            0 => Some(NameLength::Four),
+            1 => Some(NameLength::Eight),
+            2 => Some(NameLength::Twelve),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            NameLength::Four => 0,
+            NameLength::Eight => 1,
+            NameLength::Twelve => 2,
+        }
+    }
+
+    pub fn chars(self) -> usize {
+        match self {
+            NameLength::Four => 4,
+            NameLength::Eight => 8,
+            NameLength::Twelve => 12,
+        }
+    }
+
+    /// Most names one response message carries.
+    pub fn max_names(self) -> usize {
+        match self {
+            NameLength::Four => 32,
+            NameLength::Eight => 16,
+            NameLength::Twelve => 10,
+        }
+    }
+
+    /// Fits `name` to this length, padding with spaces. Characters that are
+    /// not printable ASCII are sent as `?`.
+    pub fn fit(self, name: &str) -> String {
+        let chars = self.chars();
+        let name: String = name.chars()
+            .map(|c| if c == ' ' || c.is_ascii_graphic() { c } else { '?' })
+            .take(chars)
+            .collect();
+
+        format!("{:width$}", name, width = chars)
+    }
+}
+
+/// A message of the SW-P-08 general router protocol. Destinations and
+/// sources are numbered from 0, as on the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// Asks which source is on `dest`, answered with a `Tally`.
+    Interrogate { matrix: u8, level: u8, dest: usize },
+    Connect { matrix: u8, level: u8, dest: usize, source: usize },
+    /// The source on `dest`, in reply to an `Interrogate`.
+    Tally { matrix: u8, level: u8, dest: usize, source: usize },
+    /// Sent to every controller when `dest` is switched to `source`.
+    Connected { matrix: u8, level: u8, dest: usize, source: usize },
+    TallyDumpRequest { matrix: u8, level: u8 },
+    /// The sources on consecutive destinations starting at `first_dest`.
+    TallyDump { matrix: u8, level: u8, first_dest: usize, sources: Vec<usize> },
+    SourceNamesRequest { matrix: u8, level: u8, length: NameLength },
+    DestNamesRequest { matrix: u8, length: NameLength },
+    /// Names of consecutive sources starting at `first`.
+    SourceNames { matrix: u8, level: u8, length: NameLength, first: usize, names: Vec<String> },
+    /// Names of consecutive destinations starting at `first`.
+    DestNames { matrix: u8, length: NameLength, first: usize, names: Vec<String> },
+    /// A message that is not recognised, or a recognised one that is too
+    /// short, as the command byte followed by its data.
+    Unknown(Vec<u8>),
+}
+
+impl Message {
+    /// Interprets the data of a message, its command byte followed by the
+    /// rest, as read off the wire.
+    pub fn parse(data: &[u8]) -> Message {
+        let message = match data {
+            [INTERROGATE, matrix_level, multiplier, dest] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                Some(Message::Interrogate { matrix, level, dest: dest_number(*multiplier, *dest) })
+            },
+            [CONNECT, matrix_level, multiplier, dest, source] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                let (dest, source) = (dest_number(*multiplier, *dest), source_number(*multiplier, *source));
+                Some(Message::Connect { matrix, level, dest, source })
+            },
+            [TALLY, matrix_level, multiplier, dest, source] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                let (dest, source) = (dest_number(*multiplier, *dest), source_number(*multiplier, *source));
+                Some(Message::Tally { matrix, level, dest, source })
+            },
+            [CONNECTED, matrix_level, multiplier, dest, source] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                let (dest, source) = (dest_number(*multiplier, *dest), source_number(*multiplier, *source));
+                Some(Message::Connected { matrix, level, dest, source })
+            },
+            [TALLY_DUMP_REQUEST, matrix_level] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                Some(Message::TallyDumpRequest { matrix, level })
+            },
+            [TALLY_DUMP_WORD, matrix_level, count, first_hi, first_lo, sources @ ..]
+                if sources.len() == *count as usize * 2 =>
+            {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                Some(Message::TallyDump {
+                    matrix,
+                    level,
+                    first_dest: word(*first_hi, *first_lo),
+                    sources: sources.chunks(2).map(|source| word(source[0], source[1])).collect(),
+                })
+            },
+            [SOURCE_NAMES_REQUEST, matrix_level, length] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                NameLength::parse(*length).map(|length| Message::SourceNamesRequest { matrix, level, length })
+            },
+            [DEST_NAMES_REQUEST, matrix, length] => {
+                NameLength::parse(*length).map(|length| Message::DestNamesRequest { matrix: matrix >> 4, length })
+            },
+            [SOURCE_NAMES, matrix_level, length, first_hi, first_lo, count, names @ ..] => {
+                let (matrix, level) = split_matrix_level(*matrix_level);
+                NameLength::parse(*length)
+                    .and_then(|length| parse_names(length, *count, names))
+                    .map(|(length, names)| Message::SourceNames {
+                        matrix,
+                        level,
+                        length,
+                        first: word(*first_hi, *first_lo),
+                        names,
+                    })
+            },
+            [DEST_NAMES, matrix, length, first_hi, first_lo, count, names @ ..] => {
+                NameLength::parse(*length)
+                    .and_then(|length| parse_names(length, *count, names))
+                    .map(|(length, names)| Message::DestNames {
+                        matrix: matrix >> 4,
+                        length,
+                        first: word(*first_hi, *first_lo),
+                        names,
+                    })
+            },
+            _ => None,
+        };
+
+        message.unwrap_or_else(|| Message::Unknown(data.to_vec()))
+    }
+
+    /// The data of the message as sent on the wire, its command byte followed
+    /// by the rest.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Message::Interrogate { matrix, level, dest } => {
+                debug_assert!(*dest <= MAX_PORT, "destination {} is beyond SW-P-08", dest);
+                vec![INTERROGATE, matrix_level(*matrix, *level), multiplier(*dest, 0), (dest % 128) as u8]
+            },
+            Message::Connect { matrix, level, dest, source } => crosspoint(CONNECT, *matrix, *level, *dest, *source),
+            Message::Tally { matrix, level, dest, source } => crosspoint(TALLY, *matrix, *level, *dest, *source),
+            Message::Connected { matrix, level, dest, source } => {
+                crosspoint(CONNECTED, *matrix, *level, *dest, *source)
+            },
+            Message::TallyDumpRequest { matrix, level } => vec![TALLY_DUMP_REQUEST, matrix_level(*matrix, *level)],
+            Message::TallyDump { matrix, level, first_dest, sources } => {
+                let mut data = vec![TALLY_DUMP_WORD, matrix_level(*matrix, *level), sources.len() as u8];
+                data.extend_from_slice(&to_word(*first_dest));
+                for source in sources {
+                    data.extend_from_slice(&to_word(*source));
+                }
+                data
+            },
+            Message::SourceNamesRequest { matrix, level, length } => {
+                vec![SOURCE_NAMES_REQUEST, matrix_level(*matrix, *level), length.to_byte()]
+            },
+            Message::DestNamesRequest { matrix, length } => {
+                vec![DEST_NAMES_REQUEST, matrix_level(*matrix, 0), length.to_byte()]
+            },
+            Message::SourceNames { matrix, level, length, first, names } => {
+                names_message(SOURCE_NAMES, matrix_level(*matrix, *level), *length, *first, names)
+            },
+            Message::DestNames { matrix, length, first, names } => {
+                names_message(DEST_NAMES, matrix_level(*matrix, 0), *length, *first, names)
+            },
+            Message::Unknown(data) => data.clone(),
+        }
+    }
+}
+
+fn split_matrix_level(byte: u8) -> (u8, u8) {
+    (byte >> 4, byte & 0x0F)
+}
+
+fn matrix_level(matrix: u8, level: u8) -> u8 {
+    (matrix & 0x0F) << 4 | (level & 0x0F)
+}
+
+/// The multiplier byte carries the destination DIV 128 in bits 4-6 and the
+/// source DIV 128 in bits 0-2. Bit 3 is ignored.
+fn dest_number(multiplier: u8, dest: u8) -> usize {
+    ((multiplier as usize >> 4) & 0x07) * 128 + (dest & 0x7F) as usize
+}
+
+fn source_number(multiplier: u8, source: u8) -> usize {
+    (multiplier as usize & 0x07) * 128 + (source & 0x7F) as usize
+}
+
+fn multiplier(dest: usize, source: usize) -> u8 {
+    (((dest / 128) & 0x07) << 4 | ((source / 128) & 0x07)) as u8
+}
+
+/// Ports above `MAX_PORT` cannot be sent, and would alias a lower one, so
+/// must be left out before a message is built.
+fn crosspoint(command: u8, matrix: u8, level: u8, dest: usize, source: usize) -> Vec<u8> {
+    debug_assert!(dest <= MAX_PORT && source <= MAX_PORT, "crosspoint {} {} is beyond SW-P-08", dest, source);
+
+    vec![
+        command,
+        matrix_level(matrix, level),
+        multiplier(dest, source),
+        (dest % 128) as u8,
+        (source % 128) as u8,
+    ]
+}
+
+fn word(hi: u8, lo: u8) -> usize {
+    (hi as usize) << 8 | lo as usize
+}
+
+fn to_word(n: usize) -> [u8; 2] {
+    [(n >> 8) as u8, n as u8]
+}
+
+fn parse_names(length: NameLength, count: u8, names: &[u8]) -> Option<(NameLength, Vec<String>)> {
+    if names.len() != count as usize * length.chars() {
+        return None;
+    }
+
+    let names = names.chunks(length.chars())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect();
+
+    Some((length, names))
+}
+
+fn names_message(command: u8, matrix_level: u8, length: NameLength, first: usize, names: &[String]) -> Vec<u8> {
+    let mut data = vec![command, matrix_level, length.to_byte()];
+    data.extend_from_slice(&to_word(first));
+    data.push(names.len() as u8);
+
+    for name in names {
+        data.extend_from_slice(length.fit(name).as_bytes());
+    }
+
+    data
+}
@@ -0,0 +1,186 @@
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::RecvError;
+use tokio_util::codec::{Framed};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info};
+use std::{error::Error, io};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::events::{Event};
+use crate::shared::{Shared};
+use crate::swp08::{Frame, Message, NameLength, Swp08Codec, MAX_PORT, MAX_TALLIES};
+
+/// The matrix and level the router answers on. Anything addressed to
+/// another is ignored, as a real router would for one it does not have.
+const MATRIX: u8 = 0;
+const LEVEL: u8 = 0;
+
+/// Source reported for a destination that has never been routed, or is
+/// routed to a source SW-P-08 cannot number, as SW-P-08 has no way to say a
+/// destination has no source.
+pub const NO_SOURCE: usize = MAX_PORT;
+
+/// Highest input that can be routed or reported, below `NO_SOURCE` so the
+/// two cannot be confused. Outputs go up to `MAX_PORT`.
+pub const MAX_SOURCE: usize = NO_SOURCE - 1;
+
+/// Accepts SW-P-08 controllers on `listener` until it fails.
+pub async fn serve(mut listener: TcpListener, state: Arc<Mutex<Shared>>) -> io::Result<()> {
+    info!("SW-P-08 server running on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let state = Arc::clone(&state);
+
+        tokio::spawn(async move {
+            if let Err(e) = process(state, stream, addr).await {
+                info!("SW-P-08 controller {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Serves one SW-P-08 controller until it disconnects.
+///
+/// Routes are made through the router core on behalf of the controller's
+/// address, so Videohub locks apply to it too. It is told about every route
+/// made by anyone with a `CONNECTED` message, fed from the router's events.
+pub async fn process(
+    state: Arc<Mutex<Shared>>,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> Result<(), Box<dyn Error>> {
+    info!("New SW-P-08 controller connected: {}", addr);
+
+    let mut frames = Framed::new(stream, Swp08Codec::new());
+    let mut events = state.lock().await.events.subscribe();
+
+    loop {
+        tokio::select! {
+            frame = frames.next() => match frame {
+                Some(Ok(Frame::Message(message))) => {
+                    frames.send(Frame::Ack).await?;
+
+                    debug!("SW-P-08 {:?} from {}", message, addr);
+                    for reply in handle(&state, addr, message).await {
+                        frames.send(Frame::Message(reply)).await?;
+                    }
+                },
+                Some(Ok(Frame::Corrupt)) => {
+                    debug!("damaged SW-P-08 message from {}, sending NAK", addr);
+                    frames.send(Frame::Nak).await?;
+                },
+                // Replies are not resent, so their acknowledgements need no
+                // action.
+                Some(Ok(Frame::Ack)) | Some(Ok(Frame::Nak)) => {},
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+            event = events.recv() => match event {
+                Ok(Event::Routes(routes)) => {
+                    // Ports SW-P-08 cannot number are left out rather than
+                    // sent as a lower one.
+                    for (dest, source) in routes.into_iter().filter(|&(dest, source)| dest <= MAX_PORT && source <= MAX_SOURCE) {
+                        frames.send(Frame::Message(Message::Connected { matrix: MATRIX, level: LEVEL, dest, source })).await?;
+                    }
+                },
+                Ok(_) => {},
+                // Routes were missed, so the controller is sent all of them.
+                Err(RecvError::Lagged(_)) => {
+                    let dump = {
+                        let state = state.lock().await;
+                        events = state.events.subscribe();
+                        tally_dump(&state)
+                    };
+
+                    for message in dump {
+                        frames.send(Frame::Message(message)).await?;
+                    }
+                },
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+
+    info!("SW-P-08 controller {} disconnected", addr);
+    Ok(())
+}
+
+/// Acts on a message from a controller, returning the replies to send it.
+async fn handle(state: &Arc<Mutex<Shared>>, addr: SocketAddr, message: Message) -> Vec<Message> {
+    let mut state = state.lock().await;
+
+    match message {
+        Message::Interrogate { matrix: MATRIX, level: LEVEL, dest } => {
+            tally(&state, dest).into_iter().collect()
+        },
+        Message::Connect { matrix: MATRIX, level: LEVEL, dest, source } => {
+            // An accepted route is confirmed by the CONNECTED sent to every
+            // controller, a refused one gets the route it is left with.
+            // `NO_SOURCE` is never a real input.
+            if source <= MAX_SOURCE && state.apply_routes(addr, &[(dest, source)]).await {
+                Vec::new()
+            } else {
+                debug!("refused SW-P-08 connect of source {} to destination {} from {}", source, dest, addr);
+                tally(&state, dest).into_iter().collect()
+            }
+        },
+        Message::TallyDumpRequest { matrix: MATRIX, level: LEVEL } => tally_dump(&state),
+        Message::SourceNamesRequest { matrix: MATRIX, level: LEVEL, length } => {
+            names(state.video_hub.input_labels(), length)
+                .map(|(first, names)| Message::SourceNames { matrix: MATRIX, level: LEVEL, length, first, names })
+                .collect()
+        },
+        Message::DestNamesRequest { matrix: MATRIX, length } => {
+            names(state.video_hub.output_labels(), length)
+                .map(|(first, names)| Message::DestNames { matrix: MATRIX, length, first, names })
+                .collect()
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// The source on `dest`, or `None` if there is no such destination.
+fn tally(state: &Shared, dest: usize) -> Option<Message> {
+    if dest >= state.outputs.len() || dest > MAX_PORT {
+        return None;
+    }
+
+    Some(Message::Tally { matrix: MATRIX, level: LEVEL, dest, source: source(state, dest) })
+}
+
+/// The source on every destination, as many tally dumps as it takes.
+fn tally_dump(state: &Shared) -> Vec<Message> {
+    let sources: Vec<usize> = (0..state.outputs.len().min(MAX_PORT + 1))
+        .map(|dest| source(state, dest))
+        .collect();
+
+    sources.chunks(MAX_TALLIES).enumerate()
+        .map(|(i, sources)| Message::TallyDump {
+            matrix: MATRIX,
+            level: LEVEL,
+            first_dest: i * MAX_TALLIES,
+            sources: sources.to_vec(),
+        })
+        .collect()
+}
+
+/// The source on `dest` as SW-P-08 reports it, `NO_SOURCE` if there is
+/// none or it is beyond `MAX_SOURCE`.
+fn source(state: &Shared, dest: usize) -> usize {
+    match state.video_hub.routes().get(&dest) {
+        Some(&source) if source <= MAX_SOURCE => source,
+        _ => NO_SOURCE,
+    }
+}
+
+/// Splits `labels` into as many name responses as it takes, each with the
+/// number of its first name.
+fn names(labels: &[String], length: NameLength) -> impl Iterator<Item = (usize, Vec<String>)> + '_ {
+    let labels = &labels[..labels.len().min(MAX_PORT + 1)];
+
+    labels.chunks(length.max_names()).enumerate()
+        .map(move |(i, names)| (i * length.max_names(), names.iter().map(|name| length.fit(name)).collect()))
+}
@@ -0,0 +1,20 @@
+use tokio::sync::Mutex;
+use tokio::time;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::shared::{Shared};
+
+/// How often pending routes are checked for having waited too long.
+const CHECK_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Drops routes that have been pending for longer than `timeout` for the
+/// lifetime of the router, so an abandoned preview does not wait forever.
+pub async fn run(state: Arc<Mutex<Shared>>, timeout: Duration) {
+    let mut interval = time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        state.lock().await.expire_pending(timeout).await;
+    }
+}
@@ -0,0 +1,366 @@
+use bytes::{BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio_util::codec::{Decoder, Encoder, LinesCodec};
+use std::fmt;
+use std::io;
+
+/// Longest line accepted from a client, anything longer is a broken or
+/// hostile client rather than a label.
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+pub const PROTOCOL_PREAMBLE: &'static str = "PROTOCOL PREAMBLE:";
+pub const VIDEOHUB_DEVICE: &'static str = "VIDEOHUB DEVICE:";
+pub const INPUT_LABELS: &'static str = "INPUT LABELS:";
+pub const OUTPUT_LABELS: &'static str = "OUTPUT LABELS:";
+pub const VIDEO_OUTPUT_ROUTING: &'static str = "VIDEO OUTPUT ROUTING:";
+pub const VIDEO_OUTPUT_LOCKS: &'static str = "VIDEO OUTPUT LOCKS:";
+pub const CONFIGURATION: &'static str = "CONFIGURATION:";
+// The blocks from here up to `END PRELUDE:` are the router's own, see
+// "Protocol extensions" in the README.
+pub const TAKE_MODE: &'static str = "TAKE MODE:";
+pub const PENDING_ROUTING: &'static str = "PENDING VIDEO OUTPUT ROUTING:";
+pub const TAKE: &'static str = "TAKE:";
+pub const SALVOS: &'static str = "SALVOS:";
+pub const FIRE_SALVO: &'static str = "FIRE SALVO:";
+pub const ROUTES_REFUSED: &'static str = "ROUTES REFUSED:";
+pub const SNAPSHOTS: &'static str = "SNAPSHOTS:";
+pub const SAVE_SNAPSHOT: &'static str = "SAVE SNAPSHOT:";
+pub const RECALL_SNAPSHOT: &'static str = "RECALL SNAPSHOT:";
+pub const DELETE_SNAPSHOT: &'static str = "DELETE SNAPSHOT:";
+pub const END_PRELUDE: &'static str = "END PRELUDE:";
+pub const PING: &'static str = "PING:";
+pub const ACK: &'static str = "ACK";
+pub const NAK: &'static str = "NAK";
+
+/// The lock state of an output, as sent in a `VIDEO OUTPUT LOCKS:` block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockState {
+    /// `O`, locked by the client the block is sent to.
+    Owned,
+    /// `L`, locked by another client.
+    Locked,
+    /// `U`, not locked.
+    Unlocked,
+    /// `F`, a request to remove a lock whoever holds it.
+    Force,
+}
+
+impl LockState {
+    pub fn parse(s: &str) -> Option<LockState> {
+        match s {
+            "O" => Some(LockState::Owned),
+            "L" => Some(LockState::Locked),
+            "U" => Some(LockState::Unlocked),
+            "F" => Some(LockState::Force),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LockState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            LockState::Owned => "O",
+            LockState::Locked => "L",
+            LockState::Unlocked => "U",
+            LockState::Force => "F",
+        };
+
+        f.write_str(s)
+    }
+}
+
+/// A block of the Videohub ethernet protocol: a header line, any number of
+/// body lines and a terminating blank line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Preamble { version: String },
+    /// `VIDEOHUB DEVICE:` as `key: value` pairs, in the order sent.
+    Device(Vec<(String, String)>),
+    InputLabels(Vec<(usize, String)>),
+    OutputLabels(Vec<(usize, String)>),
+    /// `(output, input)` crosspoints.
+    Routing(Vec<(usize, usize)>),
+    Locks(Vec<(usize, LockState)>),
+    /// `CONFIGURATION:` as `key: value` pairs, in the order sent.
+    Configuration(Vec<(String, String)>),
+    /// Whether each output holds new routes until they are taken.
+    TakeMode(Vec<(usize, bool)>),
+    /// `(output, input)` crosspoints waiting to be taken. Unlike the other
+    /// blocks this always lists every pending crosspoint, so an empty block
+    /// means nothing is pending.
+    PendingRouting(Vec<(usize, usize)>),
+    /// Applies the pending crosspoints on the listed outputs, or on every
+    /// output when empty.
+    Take(Vec<usize>),
+    /// The salvos that can be fired, by index.
+    Salvos(Vec<(usize, String)>),
+    /// Fires the salvo with the given name.
+    FireSalvo(String),
+    /// `(output, reason)` for each crosspoint of a fired salvo or recalled
+    /// snapshot that was not switched.
+    RoutesRefused(Vec<(usize, String)>),
+    /// The routing snapshots that can be recalled, by index.
+    Snapshots(Vec<(usize, String)>),
+    /// Saves the current routing table as the snapshot with the given name.
+    SaveSnapshot(String),
+    /// Switches every output whose route differs from the named snapshot.
+    RecallSnapshot(String),
+    DeleteSnapshot(String),
+    /// Marks the end of the status dump sent to a newly connected client.
+    EndPrelude,
+    Ping,
+    Ack,
+    Nak,
+    /// A block that is not recognised, or a recognised one whose body could
+    /// not be parsed.
+    Unknown { header: String, lines: Vec<String> },
+}
+
+impl Block {
+    /// Interprets a header and body as read off the wire.
+    pub fn parse(header: String, lines: Vec<String>) -> Block {
+        let block = match header.as_str() {
+            PROTOCOL_PREAMBLE => parse_pairs(&lines).and_then(|pairs| match pairs.as_slice() {
+                [(key, version)] if key == "Version" => Some(Block::Preamble { version: version.to_owned() }),
+                _ => None,
+            }),
+            VIDEOHUB_DEVICE => parse_pairs(&lines).map(Block::Device),
+            CONFIGURATION => parse_pairs(&lines).map(Block::Configuration),
+            INPUT_LABELS => parse_indexed(&lines, |label| Some(label.to_owned())).map(Block::InputLabels),
+            OUTPUT_LABELS => parse_indexed(&lines, |label| Some(label.to_owned())).map(Block::OutputLabels),
+            VIDEO_OUTPUT_ROUTING => parse_indexed(&lines, |input| input.parse().ok()).map(Block::Routing),
+            VIDEO_OUTPUT_LOCKS => parse_indexed(&lines, LockState::parse).map(Block::Locks),
+            TAKE_MODE => parse_indexed(&lines, |take| take.parse().ok()).map(Block::TakeMode),
+            PENDING_ROUTING => parse_indexed(&lines, |input| input.parse().ok()).map(Block::PendingRouting),
+            SALVOS => parse_indexed(&lines, |name| Some(name.to_owned())).map(Block::Salvos),
+            FIRE_SALVO => parse_name(&lines).map(Block::FireSalvo),
+            ROUTES_REFUSED => parse_indexed(&lines, |reason| Some(reason.to_owned())).map(Block::RoutesRefused),
+            SNAPSHOTS => parse_indexed(&lines, |name| Some(name.to_owned())).map(Block::Snapshots),
+            SAVE_SNAPSHOT => parse_name(&lines).map(Block::SaveSnapshot),
+            RECALL_SNAPSHOT => parse_name(&lines).map(Block::RecallSnapshot),
+            DELETE_SNAPSHOT => parse_name(&lines).map(Block::DeleteSnapshot),
+            TAKE => lines.iter().map(|line| line.trim().parse().ok()).collect::<Option<_>>().map(Block::Take),
+            END_PRELUDE if lines.is_empty() => Some(Block::EndPrelude),
+            PING if lines.is_empty() => Some(Block::Ping),
+            ACK if lines.is_empty() => Some(Block::Ack),
+            NAK if lines.is_empty() => Some(Block::Nak),
+            _ => None,
+        };
+
+        block.unwrap_or(Block::Unknown { header, lines })
+    }
+
+    /// Returns true for a block with no body, which a client sends to ask
+    /// for the current state of that block.
+    pub fn is_request(&self) -> bool {
+        match self {
+            Block::Device(pairs) | Block::Configuration(pairs) => pairs.is_empty(),
+            Block::InputLabels(labels) | Block::OutputLabels(labels)
+                | Block::Salvos(labels) | Block::Snapshots(labels) => labels.is_empty(),
+            Block::Routing(routes) | Block::PendingRouting(routes) => routes.is_empty(),
+            Block::Locks(locks) => locks.is_empty(),
+            Block::TakeMode(take) => take.is_empty(),
+            // An empty preamble has no version to parse, so is left unknown.
+            Block::Unknown { header, lines } => header == PROTOCOL_PREAMBLE && lines.is_empty(),
+            _ => false,
+        }
+    }
+
+    pub fn header(&self) -> &str {
+        match self {
+            Block::Preamble { .. } => PROTOCOL_PREAMBLE,
+            Block::Device(_) => VIDEOHUB_DEVICE,
+            Block::InputLabels(_) => INPUT_LABELS,
+            Block::OutputLabels(_) => OUTPUT_LABELS,
+            Block::Routing(_) => VIDEO_OUTPUT_ROUTING,
+            Block::Locks(_) => VIDEO_OUTPUT_LOCKS,
+            Block::Configuration(_) => CONFIGURATION,
+            Block::TakeMode(_) => TAKE_MODE,
+            Block::PendingRouting(_) => PENDING_ROUTING,
+            Block::Take(_) => TAKE,
+            Block::Salvos(_) => SALVOS,
+            Block::FireSalvo(_) => FIRE_SALVO,
+            Block::RoutesRefused(_) => ROUTES_REFUSED,
+            Block::Snapshots(_) => SNAPSHOTS,
+            Block::SaveSnapshot(_) => SAVE_SNAPSHOT,
+            Block::RecallSnapshot(_) => RECALL_SNAPSHOT,
+            Block::DeleteSnapshot(_) => DELETE_SNAPSHOT,
+            Block::EndPrelude => END_PRELUDE,
+            Block::Ping => PING,
+            Block::Ack => ACK,
+            Block::Nak => NAK,
+            Block::Unknown { header, .. } => header,
+        }
+    }
+
+    /// The body lines of the block, as sent on the wire.
+    pub fn lines(&self) -> Vec<String> {
+        match self {
+            Block::Preamble { version } => vec![format!("Version: {}", version)],
+            Block::Device(pairs) | Block::Configuration(pairs) => {
+                pairs.iter().map(|(key, value)| format!("{}: {}", key, value)).collect()
+            },
+            Block::InputLabels(labels) | Block::OutputLabels(labels)
+                | Block::Salvos(labels) | Block::RoutesRefused(labels) | Block::Snapshots(labels) => {
+                labels.iter().map(|(i, label)| format!("{} {}", i, label)).collect()
+            },
+            Block::FireSalvo(name) | Block::SaveSnapshot(name)
+                | Block::RecallSnapshot(name) | Block::DeleteSnapshot(name) => vec![name.clone()],
+            Block::Routing(routes) | Block::PendingRouting(routes) => {
+                routes.iter().map(|(output, input)| format!("{} {}", output, input)).collect()
+            },
+            Block::TakeMode(take) => {
+                take.iter().map(|(output, take)| format!("{} {}", output, take)).collect()
+            },
+            Block::Take(outputs) => outputs.iter().map(|output| output.to_string()).collect(),
+            Block::Locks(locks) => {
+                locks.iter().map(|(output, lock)| format!("{} {}", output, lock)).collect()
+            },
+            Block::EndPrelude | Block::Ping | Block::Ack | Block::Nak => Vec::new(),
+            Block::Unknown { lines, .. } => lines.clone(),
+        }
+    }
+}
+
+/// Parses `key: value` lines.
+fn parse_pairs(lines: &[String]) -> Option<Vec<(String, String)>> {
+    lines.iter()
+        .map(|line| {
+            let mut split = line.splitn(2, ':');
+            let key = split.next()?.trim();
+            let value = split.next()?.trim();
+
+            if key.is_empty() {
+                None
+            } else {
+                Some((key.to_owned(), value.to_owned()))
+            }
+        })
+        .collect()
+}
+
+/// Parses the single line naming a salvo or snapshot.
+fn parse_name(lines: &[String]) -> Option<String> {
+    match lines {
+        [name] if !name.trim().is_empty() => Some(name.trim().to_owned()),
+        _ => None,
+    }
+}
+
+/// Parses `<index> <value>` lines, returning `None` if any line is malformed.
+fn parse_indexed<T, F>(lines: &[String], parse_value: F) -> Option<Vec<(usize, T)>>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    lines.iter()
+        .map(|line| {
+            let mut split = line.trim().splitn(2, ' ');
+            let index = split.next()?.parse::<usize>().ok()?;
+            let value = split.next()?.trim();
+
+            if value.is_empty() {
+                None
+            } else {
+                Some((index, parse_value(value)?))
+            }
+        })
+        .collect()
+}
+
+/// Frames a byte stream into Videohub protocol blocks.
+pub struct VideohubCodec {
+    lines: LinesCodec,
+    block: Vec<String>,
+}
+
+impl VideohubCodec {
+    pub fn new() -> VideohubCodec {
+        VideohubCodec {
+            lines: LinesCodec::new_with_max_length(MAX_LINE_LENGTH),
+            block: Vec::new(),
+        }
+    }
+
+    /// Adds a line to the block being read, returning the block once the
+    /// blank line that ends it arrives.
+    fn push_line(&mut self, line: String) -> Option<Block> {
+        if !line.trim().is_empty() {
+            self.block.push(line);
+            return None;
+        }
+
+        if self.block.is_empty() {
+            return None;
+        }
+
+        let mut lines = std::mem::replace(&mut self.block, Vec::new());
+        let header = lines.remove(0);
+        Some(Block::parse(header, lines))
+    }
+}
+
+impl Default for VideohubCodec {
+    fn default() -> Self {
+        VideohubCodec::new()
+    }
+}
+
+impl Decoder for VideohubCodec {
+    type Item = Block;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Block>, io::Error> {
+        loop {
+            let line = self.lines.decode(src)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            match line {
+                Some(line) => {
+                    if let Some(block) = self.push_line(line) {
+                        return Ok(Some(block));
+                    }
+                },
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Block>, io::Error> {
+        if let Some(block) = self.decode(src)? {
+            return Ok(Some(block));
+        }
+
+        // A client closing the connection ends whatever block it was sending.
+        let line = self.lines.decode_eof(src)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if let Some(line) = line {
+            self.push_line(line);
+        }
+
+        Ok(self.push_line(String::new()))
+    }
+}
+
+impl Encoder for VideohubCodec {
+    type Item = Block;
+    type Error = io::Error;
+
+    fn encode(&mut self, block: Block, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let mut encoded = String::from(block.header());
+        encoded.push('\n');
+
+        for line in block.lines() {
+            encoded.push_str(&line);
+            encoded.push('\n');
+        }
+
+        encoded.push('\n');
+
+        dst.reserve(encoded.len());
+        dst.put(encoded.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,533 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+pub mod codec;
+
+pub use crate::videohub::codec::{Block, LockState, VideohubCodec};
+use crate::videohub::codec::{
+    CONFIGURATION, INPUT_LABELS, OUTPUT_LABELS, PENDING_ROUTING, PROTOCOL_PREAMBLE, TAKE_MODE,
+    VIDEOHUB_DEVICE, VIDEO_OUTPUT_LOCKS, VIDEO_OUTPUT_ROUTING,
+};
+
+/// How the router describes itself to clients in the status dump.
+///
+/// The unique ID and friendly name are saved with the rest of the state, so
+/// a generated ID stays the same and a name set by a client survives a
+/// restart. The rest comes from the config every time.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Identity {
+    /// Videohub protocol version reported in the preamble.
+    #[serde(skip)]
+    pub protocol_version: String,
+    #[serde(skip)]
+    pub model_name: String,
+    pub unique_id: String,
+    pub friendly_name: String,
+}
+
+impl Identity {
+    /// Makes up a unique ID in the 12 hex digit form real Videohubs use, from
+    /// the randomly seeded std hasher and the current time.
+    pub fn generate_unique_id() -> String {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(std::process::id());
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.write_u128(now.as_nanos());
+        }
+
+        format!("{:012X}", hasher.finish() & 0xFFFF_FFFF_FFFF)
+    }
+}
+
+/// Owner given to locks restored from the state file. Port 0 is never the
+/// address of a connected client, so restored locks show as locked to every
+/// client and stay on until forced off.
+pub const RESTORED_LOCK_OWNER: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+
+/// A crosspoint held on an output in take mode until it is taken.
+#[derive(Clone, Debug)]
+pub struct Pending {
+    pub input: usize,
+    /// When the crosspoint was requested, for dropping abandoned ones.
+    pub since: Instant,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VideoHub {
+    #[serde(default)]
+    identity: Identity,
+    #[serde(rename = "input_labels")]
+    input_lables: Vec<String>,
+    #[serde(rename = "output_labels")]
+    output_lables: Vec<String>,
+    /// The input routed to each output, by output.
+    routes: BTreeMap<usize, usize>,
+    /// The client holding the lock on each output, by output. Only which
+    /// outputs are locked is saved.
+    #[serde(with = "saved_locks")]
+    locks: BTreeMap<usize, Option<SocketAddr>>,
+    /// Whether take mode is turned on at all. Take mode settings come from
+    /// the config at startup, so are not saved.
+    #[serde(skip)]
+    take_mode: bool,
+    /// Outputs that hold new routes until they are taken, when take mode is on.
+    #[serde(skip)]
+    take_outputs: BTreeSet<usize>,
+    #[serde(skip)]
+    pending: BTreeMap<usize, Pending>,
+}
+
+impl VideoHub {
+    pub fn new(num_inputs: usize, num_outputs: usize) -> VideoHub {
+        let intial_routing = BTreeMap::new();
+        let mut intial_output_labels = Vec::with_capacity(num_outputs);
+        let mut intial_input_labels = Vec::with_capacity(num_inputs);
+        let mut intial_locks = BTreeMap::new();
+
+        for x in 0..num_inputs {
+            intial_input_labels.push(format!("NDI Input {}", x));
+        }
+
+        for x in 0..num_outputs {
+            intial_output_labels.push(format!("NDI Output {}", x));
+            intial_locks.insert(x, None);
+        }
+
+        VideoHub {
+            identity: Identity::default(),
+            input_lables: intial_input_labels,
+            output_lables: intial_output_labels,
+            routes: intial_routing,
+            locks: intial_locks,
+            take_mode: false,
+            take_outputs: BTreeSet::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Takes on the labels, routes and locks of a previously saved router,
+    /// skipping anything that no longer fits this router's size, along with
+    /// its unique ID and friendly name if it had them.
+    ///
+    /// Restored locks belong to `RESTORED_LOCK_OWNER`, as the client that
+    /// took them is not connected any more.
+    pub fn restore(&mut self, saved: VideoHub) {
+        if !saved.identity.unique_id.is_empty() {
+            self.identity.unique_id = saved.identity.unique_id;
+        }
+
+        if !saved.identity.friendly_name.is_empty() {
+            self.identity.friendly_name = saved.identity.friendly_name;
+        }
+
+        for (i, label) in saved.input_lables.into_iter().enumerate().take(self.input_lables.len()) {
+            self.input_lables[i] = label;
+        }
+
+        for (i, label) in saved.output_lables.into_iter().enumerate().take(self.output_lables.len()) {
+            self.output_lables[i] = label;
+        }
+
+        for (output, input) in saved.routes {
+            if output < self.output_lables.len() && input < self.input_lables.len() {
+                self.routes.insert(output, input);
+            }
+        }
+
+        for (output, owner) in saved.locks {
+            if let Some(lock) = self.locks.get_mut(&output) {
+                *lock = owner;
+            }
+        }
+    }
+
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = identity;
+    }
+
+    pub fn set_friendly_name(&mut self, name: String) {
+        self.identity.friendly_name = name;
+    }
+
+    /// The input routed to each output, by output.
+    pub fn routes(&self) -> &BTreeMap<usize, usize> {
+        &self.routes
+    }
+
+    /// Returns the outputs currently routed to `input`.
+    pub fn outputs_for_input(&self, input: usize) -> Vec<usize> {
+        self.routes.iter()
+            .filter(|&(_, routed)| *routed == input)
+            .map(|(output, _)| *output)
+            .collect()
+    }
+
+    pub fn preamble(&self) -> Block {
+        Block::Preamble { version: self.identity.protocol_version.clone() }
+    }
+
+    pub fn device_info(&self) -> Block {
+        Block::Device(vec![
+            ("Device present".to_owned(), "true".to_owned()),
+            ("Model name".to_owned(), self.identity.model_name.clone()),
+            ("Friendly name".to_owned(), self.identity.friendly_name.clone()),
+            ("Unique ID".to_owned(), self.identity.unique_id.clone()),
+            ("Video inputs".to_owned(), self.input_lables.len().to_string()),
+            ("Video processing units".to_owned(), "0".to_owned()),
+            ("Video outputs".to_owned(), self.output_lables.len().to_string()),
+            ("Video monitoring outputs".to_owned(), "0".to_owned()),
+            ("Serial ports".to_owned(), "0".to_owned()),
+        ])
+    }
+
+    /// Router wide settings.
+    pub fn configuration(&self) -> Block {
+        Block::Configuration(vec![
+            ("Take Mode".to_owned(), self.take_mode.to_string()),
+        ])
+    }
+
+    pub fn take_mode(&self) -> bool {
+        self.take_mode
+    }
+
+    /// Turns take mode on or off for the whole router. Turning it off drops
+    /// anything pending, returning true if there was.
+    pub fn set_take_mode(&mut self, take_mode: bool) -> bool {
+        self.take_mode = take_mode;
+        self.drop_untakeable()
+    }
+
+    /// Sets whether `output` holds new routes until they are taken, returning
+    /// true if a pending crosspoint on it was dropped.
+    pub fn set_output_take_mode(&mut self, output: usize, take: bool) -> bool {
+        if take {
+            self.take_outputs.insert(output);
+        } else {
+            self.take_outputs.remove(&output);
+        }
+
+        self.drop_untakeable()
+    }
+
+    /// Returns true if a route to `output` has to be taken before it is
+    /// applied.
+    pub fn needs_take(&self, output: usize) -> bool {
+        self.take_mode && self.take_outputs.contains(&output)
+    }
+
+    fn drop_untakeable(&mut self) -> bool {
+        let before = self.pending.len();
+        let take_mode = self.take_mode;
+        let take_outputs = &self.take_outputs;
+        self.pending.retain(|output, _| take_mode && take_outputs.contains(output));
+        self.pending.len() != before
+    }
+
+    /// Builds a `TAKE MODE:` block for just the given outputs, in output order.
+    pub fn take_mode_update(&self, outputs: &[usize]) -> Block {
+        Block::TakeMode(sorted(outputs).into_iter()
+            .filter(|&output| output < self.output_lables.len())
+            .map(|output| (output, self.take_outputs.contains(&output)))
+            .collect())
+    }
+
+    pub fn list_take_modes(&self) -> Block {
+        let outputs: Vec<usize> = (0..self.output_lables.len()).collect();
+        self.take_mode_update(&outputs)
+    }
+
+    /// Holds `input` on `output` until it is taken, replacing anything that
+    /// was already pending there.
+    pub fn stage_route(&mut self, output: usize, input: usize) {
+        self.pending.insert(output, Pending { input, since: Instant::now() });
+    }
+
+    /// Removes and returns the crosspoint pending on `output`.
+    pub fn take_pending(&mut self, output: usize) -> Option<usize> {
+        self.pending.remove(&output).map(|pending| pending.input)
+    }
+
+    pub fn pending_outputs(&self) -> Vec<usize> {
+        self.pending.keys().cloned().collect()
+    }
+
+    /// The input pending on `output`, if there is one.
+    pub fn pending_input(&self, output: usize) -> Option<usize> {
+        self.pending.get(&output).map(|pending| pending.input)
+    }
+
+    pub fn is_pending(&self, output: usize) -> bool {
+        self.pending.contains_key(&output)
+    }
+
+    /// Drops crosspoints that have been pending for longer than `timeout`,
+    /// returning the outputs they were on.
+    pub fn expire_pending(&mut self, timeout: Duration) -> Vec<usize> {
+        let expired: Vec<usize> = self.pending.iter()
+            .filter(|(_, pending)| pending.since.elapsed() >= timeout)
+            .map(|(output, _)| *output)
+            .collect();
+
+        for output in &expired {
+            self.pending.remove(output);
+        }
+
+        expired
+    }
+
+    /// Lists every pending crosspoint as a `PENDING VIDEO OUTPUT ROUTING:`
+    /// block.
+    pub fn list_pending(&self) -> Block {
+        Block::PendingRouting(self.pending.iter()
+            .map(|(output, pending)| (*output, pending.input))
+            .collect())
+    }
+
+    pub fn list_inputs(&self) -> Block {
+        let inputs: Vec<usize> = (0..self.input_lables.len()).collect();
+        self.input_label_update(&inputs)
+    }
+
+    pub fn list_outputs(&self) -> Block {
+        let outputs: Vec<usize> = (0..self.output_lables.len()).collect();
+        self.output_label_update(&outputs)
+    }
+
+    pub fn list_routes(&self) -> Block {
+        let outputs: Vec<usize> = self.routes.keys().cloned().collect();
+        self.route_update(&outputs)
+    }
+
+    /// Lists the lock table as seen by `peer`, outputs it holds are reported
+    /// as `O`, outputs held by anyone else as `L`.
+    pub fn list_locks(&self, peer: SocketAddr) -> Block {
+        let outputs: Vec<usize> = self.locks.keys().cloned().collect();
+        self.lock_update(&outputs, peer)
+    }
+
+    /// Builds a `VIDEO OUTPUT LOCKS:` block for just the given outputs as seen
+    /// by `peer`, in output order.
+    pub fn lock_update(&self, outputs: &[usize], peer: SocketAddr) -> Block {
+        Block::Locks(sorted(outputs).into_iter()
+            .filter(|output| self.locks.contains_key(output))
+            .map(|output| (output, self.lock_state(output, peer)))
+            .collect())
+    }
+
+    pub fn lock_state(&self, output: usize, peer: SocketAddr) -> LockState {
+        match self.locks.get(&output) {
+            Some(Some(owner)) if *owner == peer => LockState::Owned,
+            Some(Some(_)) => LockState::Locked,
+            _ => LockState::Unlocked,
+        }
+    }
+
+    /// The client holding the lock on `output`, if it is locked.
+    pub fn lock_owner(&self, output: usize) -> Option<SocketAddr> {
+        self.locks.get(&output).and_then(|owner| *owner)
+    }
+
+    /// Returns true if `peer` may apply the lock `state` to `output`.
+    pub fn lock_allowed(&self, output: usize, peer: SocketAddr, state: LockState) -> bool {
+        if !self.locks.contains_key(&output) {
+            return false;
+        }
+
+        match state {
+            LockState::Owned | LockState::Unlocked => !self.is_locked_by_other(output, peer),
+            LockState::Force => true,
+            LockState::Locked => false,
+        }
+    }
+
+    /// Returns true if the output is locked by a client other than `peer`.
+    pub fn is_locked_by_other(&self, output: usize, peer: SocketAddr) -> bool {
+        self.lock_state(output, peer) == LockState::Locked
+    }
+
+    /// Applies a lock request from `peer`. `O` takes ownership of the output,
+    /// `U` releases it and `F` forces the lock off regardless of owner.
+    ///
+    /// Returns false if the request is not allowed because another client
+    /// holds the lock, or the output or state are not known.
+    pub fn set_lock(&mut self, output: usize, peer: SocketAddr, state: LockState) -> bool {
+        if !self.lock_allowed(output, peer, state) {
+            return false;
+        }
+
+        let owner = if state == LockState::Owned { Some(peer) } else { None };
+        self.locks.insert(output, owner);
+
+        true
+    }
+
+    /// Releases every lock held by `peer`, returning the outputs that were
+    /// unlocked.
+    pub fn release_locks(&mut self, peer: SocketAddr) -> Vec<usize> {
+        let mut released = Vec::new();
+
+        for (output, lock) in self.locks.iter_mut() {
+            if *lock == Some(peer) {
+                *lock = None;
+                released.push(*output);
+            }
+        }
+
+        released
+    }
+
+    /// Records that `output` is now carrying `input`.
+    pub fn set_route(&mut self, output: usize, input: usize) {
+        self.routes.insert(output, input);
+    }
+
+    /// Builds a `VIDEO OUTPUT ROUTING:` block of `output input` lines for just
+    /// the given outputs, in output order.
+    pub fn route_update(&self, outputs: &[usize]) -> Block {
+        Block::Routing(sorted(outputs).into_iter()
+            .filter_map(|output| self.routes.get(&output).map(|&input| (output, input)))
+            .collect())
+    }
+
+    /// Builds an `INPUT LABELS:` block for just the given inputs.
+    pub fn input_label_update(&self, inputs: &[usize]) -> Block {
+        Block::InputLabels(label_update(&self.input_lables, inputs))
+    }
+
+    /// Builds an `OUTPUT LABELS:` block for just the given outputs.
+    pub fn output_label_update(&self, outputs: &[usize]) -> Block {
+        Block::OutputLabels(label_update(&self.output_lables, outputs))
+    }
+
+    pub fn input_labels(&self) -> &[String] {
+        &self.input_lables
+    }
+
+    pub fn output_labels(&self) -> &[String] {
+        &self.output_lables
+    }
+
+    pub fn set_input_label(&mut self, index: usize, label: String) {
+        std::mem::replace(&mut self.input_lables[index], label);
+    }
+
+    /// Adds a new input to the end of the router, returning its index.
+    pub fn add_input(&mut self, label: String) -> usize {
+        self.input_lables.push(label);
+        self.input_lables.len() - 1
+    }
+
+    pub fn set_output_label(&mut self, index: usize, label: String) {
+        self.output_lables[index] = label;
+    }
+
+    /// The current state of the block with `header` as seen by `peer`, in
+    /// reply to a status request. Returns `None` for blocks that have no
+    /// state to report.
+    pub fn status(&self, header: &str, peer: SocketAddr) -> Option<Block> {
+        match header {
+            PROTOCOL_PREAMBLE => Some(self.preamble()),
+            VIDEOHUB_DEVICE => Some(self.device_info()),
+            INPUT_LABELS => Some(self.list_inputs()),
+            OUTPUT_LABELS => Some(self.list_outputs()),
+            VIDEO_OUTPUT_ROUTING => Some(self.list_routes()),
+            VIDEO_OUTPUT_LOCKS => Some(self.list_locks(peer)),
+            CONFIGURATION => Some(self.configuration()),
+            TAKE_MODE => Some(self.list_take_modes()),
+            PENDING_ROUTING => Some(self.list_pending()),
+            _ => None,
+        }
+    }
+
+    /// The prelude sent to a client when it connects, ending with
+    /// `END PRELUDE:` so the client knows it has the complete state.
+    ///
+    /// The take mode blocks are not part of the Blackmagic protocol, so are
+    /// only sent when take mode is on, and pending routes only when there
+    /// are some. Otherwise clients go by `Take Mode:` in `CONFIGURATION:`.
+    pub fn inital_status_dump(&self, peer: SocketAddr) -> Vec<Block> {
+        let mut dump = vec![
+            self.preamble(),
+            self.device_info(),
+            self.list_inputs(),
+            self.list_outputs(),
+            self.list_routes(),
+            self.list_locks(peer),
+            self.configuration(),
+        ];
+
+        if self.take_mode {
+            dump.push(self.list_take_modes());
+
+            if !self.pending.is_empty() {
+                dump.push(self.list_pending());
+            }
+        }
+
+        dump.push(Block::EndPrelude);
+        dump
+    }
+}
+
+/// Saves locks as the list of locked outputs. Owners are client addresses
+/// that mean nothing after a restart, and a new client could be given one.
+mod saved_locks {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+
+    use super::RESTORED_LOCK_OWNER;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Saved {
+        Outputs(Vec<usize>),
+        /// State files from before locks were saved without their owner.
+        Owners(BTreeMap<usize, Option<SocketAddr>>),
+    }
+
+    pub fn serialize<S: Serializer>(locks: &BTreeMap<usize, Option<SocketAddr>>, serializer: S) -> Result<S::Ok, S::Error> {
+        let locked: Vec<usize> = locks.iter()
+            .filter(|(_, owner)| owner.is_some())
+            .map(|(output, _)| *output)
+            .collect();
+
+        locked.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<usize, Option<SocketAddr>>, D::Error> {
+        let locked = match Saved::deserialize(deserializer)? {
+            Saved::Outputs(outputs) => outputs,
+            Saved::Owners(owners) => owners.into_iter()
+                .filter(|(_, owner)| owner.is_some())
+                .map(|(output, _)| output)
+                .collect(),
+        };
+
+        Ok(locked.into_iter().map(|output| (output, Some(RESTORED_LOCK_OWNER))).collect())
+    }
+}
+
+fn label_update(lables: &[String], indexes: &[usize]) -> Vec<(usize, String)> {
+    sorted(indexes).into_iter()
+        .filter_map(|i| lables.get(i).map(|label| (i, label.clone())))
+        .collect()
+}
+
+/// Indexes in ascending order without repeats, so a block that names the same
+/// port twice is only reported once.
+fn sorted(indexes: &[usize]) -> Vec<usize> {
+    let mut indexes = indexes.to_vec();
+    indexes.sort();
+    indexes.dedup();
+    indexes
+}
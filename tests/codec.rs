@@ -0,0 +1,201 @@
+//! Tests of the Videohub block codec. Every block the codec can produce must
+//! survive being encoded and decoded again, however it is split into reads.
+
+use bytes::BytesMut;
+use quickcheck::{quickcheck, Arbitrary, Gen};
+use tokio_util::codec::{Decoder, Encoder};
+
+use ndi_router::videohub::{Block, LockState, VideohubCodec};
+
+/// Characters used for generated text. Includes the separators the protocol
+/// uses, a space and a colon, but no line breaks.
+const TEXT: &[char] = &[
+    'a', 'b', 'Z', '0', '9', ' ', ':', '-', '_', '.', '(', ')', 'é', '→',
+];
+
+const HEADER: &[char] = &['A', 'B', 'Q', 'X', ' '];
+
+fn pick<G: Gen>(g: &mut G, chars: &[char]) -> char {
+    chars[usize::arbitrary(g) % chars.len()]
+}
+
+/// Text that reads back the same after the codec trims it, never empty.
+fn text<G: Gen>(g: &mut G, chars: &[char]) -> String {
+    let len = 1 + usize::arbitrary(g) % 16;
+    let s: String = (0..len).map(|_| pick(g, chars)).collect();
+
+    match s.trim() {
+        "" => "x".to_owned(),
+        trimmed => trimmed.to_owned(),
+    }
+}
+
+fn key<G: Gen>(g: &mut G) -> String {
+    let chars: Vec<char> = TEXT.iter().cloned().filter(|&c| c != ':').collect();
+    text(g, &chars)
+}
+
+fn pairs<G: Gen>(g: &mut G) -> Vec<(String, String)> {
+    let len = usize::arbitrary(g) % 8;
+    (0..len).map(|_| (key(g), text(g, TEXT))).collect()
+}
+
+fn labels<G: Gen>(g: &mut G) -> Vec<(usize, String)> {
+    let len = usize::arbitrary(g) % 8;
+    (0..len).map(|_| (usize::arbitrary(g), text(g, TEXT))).collect()
+}
+
+#[derive(Clone, Debug)]
+struct ArbitraryBlock(Block);
+
+impl Arbitrary for ArbitraryBlock {
+    fn arbitrary<G: Gen>(g: &mut G) -> ArbitraryBlock {
+        let block = match u8::arbitrary(g) % 22 {
+            0 => Block::Preamble { version: text(g, TEXT) },
+            1 => Block::Device(pairs(g)),
+            2 => Block::InputLabels(labels(g)),
+            3 => Block::OutputLabels(labels(g)),
+            4 => Block::Routing(Vec::<(usize, usize)>::arbitrary(g)),
+            5 => {
+                let states = [LockState::Owned, LockState::Locked, LockState::Unlocked, LockState::Force];
+                let len = usize::arbitrary(g) % 8;
+                Block::Locks((0..len).map(|_| (usize::arbitrary(g), states[usize::arbitrary(g) % 4])).collect())
+            },
+            6 => Block::Configuration(pairs(g)),
+            7 => Block::Ping,
+            8 => Block::Ack,
+            9 => Block::Nak,
+            10 => Block::EndPrelude,
+            11 => Block::TakeMode(Vec::<(usize, bool)>::arbitrary(g)),
+            12 => Block::PendingRouting(Vec::<(usize, usize)>::arbitrary(g)),
+            13 => Block::Take(Vec::<usize>::arbitrary(g)),
+            14 => Block::Salvos(labels(g)),
+            15 => Block::FireSalvo(text(g, TEXT)),
+            16 => Block::RoutesRefused(labels(g)),
+            17 => Block::Snapshots(labels(g)),
+            18 => Block::SaveSnapshot(text(g, TEXT)),
+            19 => Block::RecallSnapshot(text(g, TEXT)),
+            20 => Block::DeleteSnapshot(text(g, TEXT)),
+            _ => {
+                // Headers of known blocks are kept out, an unknown block is
+                // anything the router does not understand.
+                let header = format!("{}:", text(g, HEADER));
+                let len = usize::arbitrary(g) % 4;
+                let lines = (0..len).map(|_| text(g, TEXT)).collect();
+                Block::Unknown { header, lines }
+            },
+        };
+
+        ArbitraryBlock(block)
+    }
+}
+
+fn encode(blocks: &[Block]) -> BytesMut {
+    let mut codec = VideohubCodec::new();
+    let mut buf = BytesMut::new();
+
+    for block in blocks {
+        codec.encode(block.clone(), &mut buf).unwrap();
+    }
+
+    buf
+}
+
+/// Decodes `bytes` fed to the codec `chunk` bytes at a time.
+fn decode(bytes: &[u8], chunk: usize) -> Vec<Block> {
+    let mut codec = VideohubCodec::new();
+    let mut buf = BytesMut::new();
+    let mut blocks = Vec::new();
+
+    for piece in bytes.chunks(chunk.max(1)) {
+        buf.extend_from_slice(piece);
+        while let Some(block) = codec.decode(&mut buf).unwrap() {
+            blocks.push(block);
+        }
+    }
+
+    while let Some(block) = codec.decode_eof(&mut buf).unwrap() {
+        blocks.push(block);
+    }
+
+    blocks
+}
+
+fn decode_str(s: &str) -> Vec<Block> {
+    decode(s.as_bytes(), s.len())
+}
+
+quickcheck! {
+    fn blocks_round_trip(blocks: Vec<ArbitraryBlock>, chunk: usize) -> bool {
+        let blocks: Vec<Block> = blocks.into_iter().map(|ArbitraryBlock(block)| block).collect();
+        let bytes = encode(&blocks);
+
+        decode(&bytes, chunk % 64) == blocks
+    }
+
+    fn reencoding_decoded_bytes_is_stable(blocks: Vec<ArbitraryBlock>) -> bool {
+        let blocks: Vec<Block> = blocks.into_iter().map(|ArbitraryBlock(block)| block).collect();
+        let bytes = encode(&blocks);
+
+        encode(&decode(&bytes, bytes.len())) == bytes
+    }
+}
+
+#[test]
+fn decodes_a_routing_request() {
+    assert_eq!(
+        decode_str("VIDEO OUTPUT ROUTING:\n0 5\n3 1\n\n"),
+        vec![Block::Routing(vec![(0, 5), (3, 1)])],
+    );
+}
+
+#[test]
+fn decodes_labels_with_spaces() {
+    assert_eq!(
+        decode_str("INPUT LABELS:\n2 Camera 2 (wide)\n\n"),
+        vec![Block::InputLabels(vec![(2, "Camera 2 (wide)".to_owned())])],
+    );
+}
+
+#[test]
+fn decodes_crlf_and_repeated_blank_lines() {
+    assert_eq!(
+        decode_str("\r\n\r\nPING:\r\n\r\n\r\nVIDEO OUTPUT LOCKS:\r\n1 F\r\n\r\n"),
+        vec![Block::Ping, Block::Locks(vec![(1, LockState::Force)])],
+    );
+}
+
+#[test]
+fn malformed_bodies_are_unknown() {
+    assert_eq!(
+        decode_str("VIDEO OUTPUT ROUTING:\n0 five\n\n"),
+        vec![Block::Unknown {
+            header: "VIDEO OUTPUT ROUTING:".to_owned(),
+            lines: vec!["0 five".to_owned()],
+        }],
+    );
+}
+
+#[test]
+fn decodes_take_blocks() {
+    assert_eq!(
+        decode_str("TAKE MODE:\n0 true\n1 false\n\nTAKE:\n0\n\nTAKE:\n\n"),
+        vec![
+            Block::TakeMode(vec![(0, true), (1, false)]),
+            Block::Take(vec![0]),
+            Block::Take(vec![]),
+        ],
+    );
+}
+
+#[test]
+fn a_block_cut_off_by_eof_is_still_decoded() {
+    assert_eq!(decode_str("PING:"), vec![Block::Ping]);
+}
+
+#[test]
+fn encodes_blocks_with_a_trailing_blank_line() {
+    let bytes = encode(&[Block::Preamble { version: "2.7".to_owned() }, Block::Ack]);
+
+    assert_eq!(&bytes[..], &b"PROTOCOL PREAMBLE:\nVersion: 2.7\n\nACK\n\n"[..]);
+}
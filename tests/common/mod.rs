@@ -0,0 +1,349 @@
+//! A router on a simulated NDI network and a Videohub client to drive it,
+//! shared by the end to end tests.
+
+#![allow(dead_code)]
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::stream::StreamExt;
+use tokio::time;
+use tokio_util::codec::{Framed, LinesCodec};
+use futures::SinkExt;
+use hyper::{Body, Client as HttpClient, HeaderMap, Method, Request, StatusCode};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use serde_json::Value;
+use std::{env, fs, process};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ndi_router::start;
+use ndi_router::config::{Config};
+use ndi_router::server::{serve};
+use ndi_router::shared::{Shared};
+use ndi_router::http;
+use ndi_router::swp08::{self, Frame, Message, Swp08Codec};
+use ndi_router::ember::{self, Element, Packet, S101Codec};
+use ndi_router::ndi::{SimulatedBackend};
+
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+pub const NUM_OUTPUTS: usize = 4;
+
+pub struct Router {
+    pub addr: SocketAddr,
+    pub http_addr: SocketAddr,
+    pub swp08_addr: SocketAddr,
+    pub ember_addr: SocketAddr,
+    pub backend: SimulatedBackend,
+    pub state: Arc<Mutex<Shared>>,
+    pub dir: PathBuf,
+    pub config: Config,
+}
+
+impl Router {
+    /// Starts a router on an ephemeral port with `sources` on the simulated
+    /// network, waiting for them all to be discovered.
+    pub async fn start(name: &str, sources: &[&str]) -> Router {
+        Router::start_with(name, sources, Config { outputs: NUM_OUTPUTS, ..Config::default() }).await
+    }
+
+    /// Starts a router as `start` does, with settings from `config`.
+    pub async fn start_with(name: &str, sources: &[&str], config: Config) -> Router {
+        let dir = env::temp_dir().join(format!("ndi-router-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let backend = SimulatedBackend::new();
+        for (i, source) in sources.iter().enumerate() {
+            backend.add_source(source, &format!("10.0.0.{}:5961", i + 1));
+        }
+
+        Router::start_in(dir, backend, sources.len(), config).await
+    }
+
+    /// Starts a router that keeps the state of a previous one, as if the
+    /// service had been restarted.
+    pub async fn restart(self) -> Router {
+        self.flush_state().await;
+        let num_inputs = self.state.lock().await.inputs.len();
+        Router::start_in(self.dir, self.backend, num_inputs, self.config).await
+    }
+
+    pub async fn start_in(dir: PathBuf, backend: SimulatedBackend, num_inputs: usize, config: Config) -> Router {
+        let config = Config {
+            source_map: dir.join("sources.json"),
+            state_file: dir.join("state.json"),
+            snapshots: dir.join("snapshots.json"),
+            ..config
+        };
+
+        let state = start(&backend, &config).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::clone(&state)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_addr = listener.local_addr().unwrap();
+        tokio::spawn(http::serve(listener, Arc::clone(&state)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let swp08_addr = listener.local_addr().unwrap();
+        tokio::spawn(swp08::server::serve(listener, Arc::clone(&state)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ember_addr = listener.local_addr().unwrap();
+        tokio::spawn(ember::server::serve(listener, Arc::clone(&state)));
+
+        let router = Router { addr, http_addr, swp08_addr, ember_addr, backend, state, dir, config };
+        router.wait_for_inputs(num_inputs).await;
+        router
+    }
+
+    /// Makes a request to the HTTP API, returning the status and JSON body.
+    pub async fn http(&self, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{}", self.http_addr, path))
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+
+        let request = async {
+            let res = HttpClient::new().request(req).await.unwrap();
+            let status = res.status();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            (status, serde_json::from_slice(&bytes).unwrap())
+        };
+
+        time::timeout(TIMEOUT, request).await.expect("timed out waiting for the HTTP API")
+    }
+
+    /// Fetches `path` from the HTTP server as text, returning the status and
+    /// headers with it.
+    pub async fn get(&self, path: &str) -> (StatusCode, HeaderMap, String) {
+        let uri = format!("http://{}{}", self.http_addr, path).parse().unwrap();
+
+        let request = async {
+            let res = HttpClient::new().get(uri).await.unwrap();
+            let (parts, body) = res.into_parts();
+            let bytes = hyper::body::to_bytes(body).await.unwrap();
+            (parts.status, parts.headers, String::from_utf8(bytes.to_vec()).unwrap())
+        };
+
+        time::timeout(TIMEOUT, request).await.expect("timed out waiting for the HTTP server")
+    }
+
+    /// Subscribes to the event stream, returning it with the snapshot it
+    /// starts with.
+    pub async fn events(&self) -> (Events, Value) {
+        let url = format!("ws://{}/api/events", self.http_addr);
+        let connect = tokio_tungstenite::connect_async(url.as_str());
+        let (ws, _) = time::timeout(TIMEOUT, connect).await
+            .expect("timed out connecting to the event stream")
+            .unwrap();
+
+        let mut events = Events { ws };
+        let snapshot = events.next().await;
+        assert_eq!(snapshot["type"], "snapshot");
+
+        (events, snapshot)
+    }
+
+    /// Waits until the state file has been written with every change so far.
+    pub async fn flush_state(&self) {
+        let state = self.state.lock().await;
+        time::timeout(TIMEOUT, state.state_file.flush()).await.expect("timed out writing the state file");
+    }
+
+    pub async fn wait_for_inputs(&self, num_inputs: usize) {
+        let discovered = async {
+            while self.state.lock().await.inputs.iter().filter(|input| input.online).count() < num_inputs {
+                time::delay_for(Duration::from_millis(10)).await;
+            }
+        };
+
+        time::timeout(TIMEOUT, discovered).await.expect("sources were not discovered");
+    }
+}
+
+pub struct Client {
+    pub lines: Framed<TcpStream, LinesCodec>,
+    pub dump: Vec<String>,
+}
+
+impl Client {
+    /// Connects to the router and reads the initial status dump, up to and
+    /// including `END PRELUDE:`. A ping is exchanged so the router has
+    /// registered the client for broadcasts by the time this returns.
+    pub async fn connect(router: &Router) -> Client {
+        let stream = TcpStream::connect(router.addr).await.unwrap();
+        let mut client = Client { lines: Framed::new(stream, LinesCodec::new()), dump: Vec::new() };
+
+        loop {
+            let block = client.read_block().await;
+            let end = block == "END PRELUDE:";
+            client.dump.push(block);
+
+            if end {
+                break;
+            }
+        }
+
+        client.send("PING:\n").await;
+        assert_eq!(client.read_block().await, "ACK");
+
+        client
+    }
+
+    /// Sends `block`, which should end in a newline, followed by the blank
+    /// line that terminates it.
+    pub async fn send(&mut self, block: &str) {
+        self.lines.send(block.to_owned()).await.unwrap();
+    }
+
+    /// Reads the next block, without its terminating blank line.
+    pub async fn read_block(&mut self) -> String {
+        let lines = &mut self.lines;
+        let read = async {
+            let mut block = Vec::new();
+
+            while let Some(line) = lines.next().await {
+                let line = line.unwrap();
+
+                if !line.is_empty() {
+                    block.push(line);
+                } else if !block.is_empty() {
+                    break;
+                }
+            }
+
+            block.join("\n")
+        };
+
+        time::timeout(TIMEOUT, read).await.expect("timed out waiting for a block")
+    }
+
+    /// Checks nothing has been sent to the client.
+    pub async fn assert_nothing_sent(&mut self) {
+        let read = time::timeout(Duration::from_millis(200), self.lines.next()).await;
+        assert!(read.is_err(), "unexpected message {:?}", read);
+    }
+}
+
+pub struct Events {
+    pub ws: WebSocketStream<TcpStream>,
+}
+
+impl Events {
+    /// Reads the next event from the stream.
+    pub async fn next(&mut self) -> Value {
+        let ws = &mut self.ws;
+        let read = async {
+            loop {
+                match ws.next().await.expect("event stream closed").unwrap() {
+                    WsMessage::Text(text) => return serde_json::from_str(&text).unwrap(),
+                    _ => continue,
+                }
+            }
+        };
+
+        time::timeout(TIMEOUT, read).await.expect("timed out waiting for an event")
+    }
+}
+
+/// A SW-P-08 controller.
+pub struct Probel {
+    pub frames: Framed<TcpStream, Swp08Codec>,
+}
+
+impl Probel {
+    /// Connects to the router's SW-P-08 server, waiting until the router
+    /// has started serving the connection.
+    pub async fn connect(router: &Router) -> Probel {
+        let stream = TcpStream::connect(router.swp08_addr).await.unwrap();
+        let mut probel = Probel { frames: Framed::new(stream, Swp08Codec::new()) };
+
+        probel.send(Message::TallyDumpRequest { matrix: 15, level: 15 }).await;
+        assert_eq!(probel.read().await, Frame::Ack);
+
+        probel
+    }
+
+    pub async fn send(&mut self, message: Message) {
+        self.frames.send(Frame::Message(message)).await.unwrap();
+    }
+
+    pub async fn read(&mut self) -> Frame {
+        let read = self.frames.next();
+        time::timeout(TIMEOUT, read).await
+            .expect("timed out waiting for a SW-P-08 message")
+            .expect("SW-P-08 connection closed")
+            .unwrap()
+    }
+
+    /// Reads the next message, which should be `message`.
+    pub async fn expect(&mut self, message: Message) {
+        assert_eq!(self.read().await, Frame::Message(message));
+    }
+
+    /// Checks nothing has been sent to the controller.
+    pub async fn assert_nothing_sent(&mut self) {
+        let read = time::timeout(Duration::from_millis(200), self.frames.next()).await;
+        assert!(read.is_err(), "unexpected message {:?}", read);
+    }
+}
+
+/// An Ember+ consumer.
+pub struct Consumer {
+    pub packets: Framed<TcpStream, S101Codec>,
+}
+
+impl Consumer {
+    /// Connects to the router's Ember+ provider, waiting until the router
+    /// has started serving the connection.
+    pub async fn connect(router: &Router) -> Consumer {
+        let stream = TcpStream::connect(router.ember_addr).await.unwrap();
+        let mut consumer = Consumer { packets: Framed::new(stream, S101Codec::new()) };
+
+        consumer.packets.send(Packet::KeepAliveRequest).await.unwrap();
+        assert_eq!(consumer.read_packet().await, Packet::KeepAliveResponse);
+
+        consumer
+    }
+
+    /// Sends `elements` in one Glow root.
+    pub async fn send(&mut self, elements: &[Element]) {
+        self.packets.send(Packet::Ember(ember::encode_root(elements))).await.unwrap();
+    }
+
+    pub async fn read_packet(&mut self) -> Packet {
+        let read = self.packets.next();
+        time::timeout(TIMEOUT, read).await
+            .expect("timed out waiting for an Ember+ packet")
+            .expect("Ember+ connection closed")
+            .unwrap()
+    }
+
+    /// Reads the elements of the next Glow message.
+    pub async fn read(&mut self) -> Vec<Element> {
+        match self.read_packet().await {
+            Packet::Ember(data) => ember::decode_root(&data).unwrap(),
+            packet => panic!("expected a Glow message, got {:?}", packet),
+        }
+    }
+
+    /// Checks nothing has been sent to the consumer.
+    pub async fn assert_nothing_sent(&mut self) {
+        let read = time::timeout(Duration::from_millis(200), self.packets.next()).await;
+        assert!(read.is_err(), "unexpected packet {:?}", read);
+    }
+}
+
+/// Picks the unique ID out of a `VIDEOHUB DEVICE:` block.
+pub fn unique_id(block: &str) -> String {
+    block.lines()
+        .find(|line| line.starts_with("Unique ID: "))
+        .map(|line| line["Unique ID: ".len()..].to_owned())
+        .expect("no unique ID in device block")
+}
@@ -0,0 +1,275 @@
+//! Tests of the BER, S101 and Glow encodings, and end to end tests of Ember+
+//! consumers routing alongside Videohub clients.
+
+mod common;
+
+use bytes::BytesMut;
+use quickcheck::quickcheck;
+use tokio_util::codec::{Decoder, Encoder};
+
+use ndi_router::ember::{
+    self, Connection, Element, Label, MatrixContents, NodeContents, Packet, ParameterContents,
+    ParameterValue, Path, S101Codec, Tlv,
+};
+use ndi_router::ember::s101::crc;
+
+use common::{Client, Consumer, Router, NUM_OUTPUTS};
+
+fn encode(packets: &[Packet]) -> BytesMut {
+    let mut codec = S101Codec::new();
+    let mut buf = BytesMut::new();
+
+    for packet in packets {
+        codec.encode(packet.clone(), &mut buf).unwrap();
+    }
+
+    buf
+}
+
+/// Decodes `bytes` fed to the codec `chunk` bytes at a time.
+fn decode(bytes: &[u8], chunk: usize) -> Vec<Packet> {
+    let mut codec = S101Codec::new();
+    let mut buf = BytesMut::new();
+    let mut packets = Vec::new();
+
+    for piece in bytes.chunks(chunk.max(1)) {
+        buf.extend_from_slice(piece);
+        while let Some(packet) = codec.decode(&mut buf).unwrap() {
+            packets.push(packet);
+        }
+    }
+
+    packets
+}
+
+fn get_directory(path: &[u32]) -> Element {
+    Element::Node {
+        path: Path::Qualified(path.to_vec()),
+        contents: None,
+        children: vec![Element::Command(ember::COMMAND_GET_DIRECTORY)],
+    }
+}
+
+fn connect(target: u32, source: u32) -> Element {
+    Element::Matrix {
+        path: Path::Qualified(vec![1, 1]),
+        contents: None,
+        children: Vec::new(),
+        connections: vec![Connection { target, sources: vec![source], operation: None, disposition: None }],
+    }
+}
+
+fn connection(target: u32, sources: &[u32], disposition: i64) -> Element {
+    Element::Matrix {
+        path: Path::Qualified(vec![1, 1]),
+        contents: None,
+        children: Vec::new(),
+        connections: vec![Connection { target, sources: sources.to_vec(), operation: None, disposition: Some(disposition) }],
+    }
+}
+
+fn label(path: &[u32], value: &str) -> Element {
+    Element::Parameter {
+        path: Path::Qualified(path.to_vec()),
+        contents: Some(ParameterContents {
+            value: Some(ParameterValue::String(value.to_owned())),
+            ..ParameterContents::default()
+        }),
+        children: Vec::new(),
+    }
+}
+
+quickcheck! {
+    fn packets_round_trip(messages: Vec<Vec<u8>>, chunk: usize) -> bool {
+        let packets: Vec<Packet> = messages.into_iter().map(Packet::Ember).collect();
+        decode(&encode(&packets), chunk % 64) == packets
+    }
+
+    fn integers_round_trip(n: i64) -> bool {
+        let bytes = Tlv::integer(n).to_bytes();
+        Tlv::decode(&bytes).unwrap().0.as_integer() == Some(n)
+    }
+
+    fn paths_round_trip(path: Vec<u32>) -> bool {
+        let bytes = Tlv::relative_oid(&path).to_bytes();
+        Tlv::decode(&bytes).unwrap().0.as_relative_oid() == Some(path)
+    }
+}
+
+#[test]
+fn crc_is_ccitt() {
+    assert_eq!(crc(b"123456789"), 0x906E);
+}
+
+#[test]
+fn long_messages_are_split_and_reserved_bytes_escaped() {
+    let data: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+    let bytes = encode(&[Packet::Ember(data.clone()), Packet::KeepAliveRequest]);
+
+    // Three frames for the message and one for the keep-alive, with no
+    // frame markers other than their own.
+    assert_eq!(bytes.iter().filter(|&&byte| byte == 0xFE).count(), 4);
+    assert_eq!(bytes.iter().filter(|&&byte| byte == 0xFF).count(), 4);
+    assert_eq!(decode(&bytes, 7), vec![Packet::Ember(data), Packet::KeepAliveRequest]);
+}
+
+#[test]
+fn damaged_frames_are_dropped() {
+    let mut bytes = encode(&[Packet::Ember(vec![1, 2, 3]), Packet::Ember(vec![4, 5, 6])]);
+    bytes[10] ^= 0x01;
+
+    assert_eq!(decode(&bytes, bytes.len()), vec![Packet::Ember(vec![4, 5, 6])]);
+}
+
+#[test]
+fn encodes_a_get_directory() {
+    assert_eq!(
+        ember::encode_root(&[Element::Command(ember::COMMAND_GET_DIRECTORY)]),
+        vec![0x60, 0x0B, 0x6B, 0x09, 0xA0, 0x07, 0x62, 0x05, 0xA0, 0x03, 0x02, 0x01, 0x20],
+    );
+}
+
+#[test]
+fn decodes_indefinite_lengths() {
+    let bytes = [
+        0x60, 0x80, 0x6B, 0x80, 0xA0, 0x80, 0x62, 0x80, 0xA0, 0x80, 0x02, 0x01, 0x20,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    assert_eq!(ember::decode_root(&bytes), Ok(vec![Element::Command(ember::COMMAND_GET_DIRECTORY)]));
+}
+
+#[test]
+fn elements_round_trip() {
+    let elements = vec![
+        Element::Node {
+            path: Path::Number(1),
+            contents: Some(NodeContents {
+                identifier: Some("router".to_owned()),
+                description: Some("Studio".to_owned()),
+                is_root: Some(true),
+                is_online: Some(true),
+            }),
+            children: vec![Element::Matrix {
+                path: Path::Number(1),
+                contents: Some(MatrixContents {
+                    identifier: Some("matrix".to_owned()),
+                    description: None,
+                    matrix_type: Some(ember::MATRIX_TYPE_ONE_TO_N),
+                    addressing_mode: Some(ember::ADDRESSING_LINEAR),
+                    target_count: Some(300),
+                    source_count: Some(2),
+                    labels: vec![Label { base_path: vec![1, 2], description: "Labels".to_owned() }],
+                }),
+                children: Vec::new(),
+                connections: vec![
+                    Connection { target: 0, sources: vec![1], operation: None, disposition: Some(ember::DISPOSITION_TALLY) },
+                    Connection { target: 299, sources: Vec::new(), operation: Some(ember::OPERATION_ABSOLUTE), disposition: None },
+                ],
+            }],
+        },
+        label(&[1, 2, 2, 200], "Caméra"),
+        Element::Command(ember::COMMAND_SUBSCRIBE),
+    ];
+
+    assert_eq!(ember::decode_root(&ember::encode_root(&elements)), Ok(elements));
+}
+
+#[tokio::test]
+async fn consumers_can_walk_the_tree() {
+    let router = Router::start("ember-walk", &["CAM 1", "CAM 2"]).await;
+    let mut consumer = Consumer::connect(&router).await;
+
+    consumer.send(&[Element::Command(ember::COMMAND_GET_DIRECTORY)]).await;
+    match &consumer.read().await[..] {
+        [Element::Node { path: Path::Number(1), contents: Some(contents), .. }] => {
+            assert_eq!(contents.identifier.as_deref(), Some("router"));
+            assert_eq!(contents.is_root, Some(true));
+        },
+        elements => panic!("unexpected reply {:?}", elements),
+    }
+
+    consumer.send(&[get_directory(&[1])]).await;
+    match &consumer.read().await[..] {
+        [Element::Node { path: Path::Qualified(path), children, .. }] => {
+            assert_eq!(path, &[1]);
+            assert!(matches!(&children[..], [Element::Matrix { path: Path::Number(1), .. }, Element::Node { path: Path::Number(2), .. }]));
+        },
+        elements => panic!("unexpected reply {:?}", elements),
+    }
+
+    consumer.send(&[get_directory(&[1, 1])]).await;
+    match &consumer.read().await[..] {
+        [Element::Matrix { contents: Some(contents), connections, .. }] => {
+            assert_eq!(contents.target_count, Some(NUM_OUTPUTS as i64));
+            assert_eq!(contents.source_count, Some(2));
+            assert_eq!(contents.labels, vec![Label { base_path: vec![1, 2], description: "Labels".to_owned() }]);
+            assert_eq!(connections.len(), NUM_OUTPUTS);
+            assert!(connections.iter().all(|connection| connection.sources.is_empty()));
+        },
+        elements => panic!("unexpected reply {:?}", elements),
+    }
+
+    consumer.send(&[get_directory(&[1, 2, 2])]).await;
+    match &consumer.read().await[..] {
+        [Element::Node { children, .. }] => {
+            let labels: Vec<_> = children.iter()
+                .map(|child| match child {
+                    Element::Parameter { contents: Some(ParameterContents { value: Some(ParameterValue::String(label)), .. }), .. } => label.as_str(),
+                    child => panic!("unexpected label {:?}", child),
+                })
+                .collect();
+            assert_eq!(labels, vec!["CAM 1", "CAM 2"]);
+        },
+        elements => panic!("unexpected reply {:?}", elements),
+    }
+
+    // Paths that do not exist are not answered.
+    consumer.send(&[get_directory(&[1, 2, 1, 99]), get_directory(&[7])]).await;
+    consumer.assert_nothing_sent().await;
+}
+
+#[tokio::test]
+async fn connections_made_by_consumers_reach_everyone() {
+    let router = Router::start("ember-connect", &["CAM 1", "CAM 2"]).await;
+    let mut consumer = Consumer::connect(&router).await;
+    let mut watcher = Consumer::connect(&router).await;
+    let mut panel = Client::connect(&router).await;
+
+    consumer.send(&[connect(2, 1)]).await;
+    assert_eq!(consumer.read().await, vec![connection(2, &[1], ember::DISPOSITION_MODIFIED)]);
+    assert_eq!(watcher.read().await, vec![connection(2, &[1], ember::DISPOSITION_MODIFIED)]);
+    assert_eq!(panel.read_block().await, "VIDEO OUTPUT ROUTING:\n2 1");
+    assert_eq!(router.backend.routed_source("NDI output 2"), Some("CAM 2".to_owned()));
+
+    // Connections that cannot be made are answered with what the target has.
+    consumer.send(&[connect(2, 9)]).await;
+    assert_eq!(consumer.read().await, vec![connection(2, &[1], ember::DISPOSITION_TALLY)]);
+    panel.assert_nothing_sent().await;
+    watcher.assert_nothing_sent().await;
+}
+
+#[tokio::test]
+async fn consumers_follow_videohub_routes_locks_and_labels() {
+    let router = Router::start("ember-follow", &["CAM 1", "CAM 2"]).await;
+    let mut consumer = Consumer::connect(&router).await;
+    let mut panel = Client::connect(&router).await;
+
+    panel.send("VIDEO OUTPUT ROUTING:\n0 1\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    assert_eq!(consumer.read().await, vec![connection(0, &[1], ember::DISPOSITION_MODIFIED)]);
+
+    panel.send("VIDEO OUTPUT LOCKS:\n0 O\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    consumer.send(&[connect(0, 0)]).await;
+    assert_eq!(consumer.read().await, vec![connection(0, &[1], ember::DISPOSITION_LOCKED)]);
+    panel.assert_nothing_sent().await;
+
+    panel.send("OUTPUT LABELS:\n1 PGM\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    assert_eq!(consumer.read().await, vec![label(&[1, 2, 1, 1], "PGM")]);
+
+    panel.send("INPUT LABELS:\n0 Wide\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    assert_eq!(consumer.read().await, vec![label(&[1, 2, 2, 0], "Wide")]);
+}
@@ -0,0 +1,206 @@
+//! End to end tests of the HTTP API, checking changes made over HTTP reach
+//! Videohub panels and the other way round.
+
+mod common;
+
+use hyper::{Method, StatusCode};
+use serde_json::json;
+
+use common::{Client, Router};
+
+#[tokio::test]
+async fn lists_inputs_and_outputs() {
+    let router = Router::start("http-list", &["CAM 1", "CAM 2"]).await;
+
+    let (status, inputs) = router.http(Method::GET, "/api/inputs", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(inputs, json!([
+        { "index": 0, "label": "CAM 1", "ndi_name": "CAM 1", "ip": "10.0.0.1:5961", "online": true },
+        { "index": 1, "label": "CAM 2", "ndi_name": "CAM 2", "ip": "10.0.0.2:5961", "online": true },
+    ]));
+
+    let (status, output) = router.http(Method::GET, "/api/outputs/3", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(output, json!({
+        "index": 3,
+        "label": "NDI Output 3",
+        "ndi_name": "NDI output 3",
+        "input": null,
+        "lock": "unlocked",
+        "take_mode": false,
+        "pending": null,
+    }));
+
+    let (status, _) = router.http(Method::GET, "/api/outputs/4", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = router.http(Method::GET, "/api/nothing", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn routes_set_over_http_reach_panels() {
+    let router = Router::start("http-routes", &["CAM 1", "CAM 2"]).await;
+    let mut panel = Client::connect(&router).await;
+
+    let routes = json!([{ "output": 2, "input": 1 }, { "output": 0, "input": 0 }]);
+    let (status, body) = router.http(Method::POST, "/api/routes", Some(routes)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([{ "output": 0, "input": 0 }, { "output": 2, "input": 1 }]));
+    assert_eq!(panel.read_block().await, "VIDEO OUTPUT ROUTING:\n0 0\n2 1");
+    assert_eq!(router.backend.routed_source("NDI output 2"), Some("CAM 2".to_owned()));
+
+    let bad = json!([{ "output": 1, "input": 1 }, { "output": 1, "input": 7 }]);
+    let (status, _) = router.http(Method::POST, "/api/routes", Some(bad)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = router.http(Method::POST, "/api/routes", Some(json!({ "output": 1 }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    panel.assert_nothing_sent().await;
+
+    panel.send("VIDEO OUTPUT ROUTING:\n1 1\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    let (_, routes) = router.http(Method::GET, "/api/routes", None).await;
+    assert_eq!(routes, json!([
+        { "output": 0, "input": 0 },
+        { "output": 1, "input": 1 },
+        { "output": 2, "input": 1 },
+    ]));
+}
+
+#[tokio::test]
+async fn labels_set_over_http_reach_panels() {
+    let router = Router::start("http-labels", &["CAM 1", "CAM 2"]).await;
+    let mut panel = Client::connect(&router).await;
+
+    let (status, body) = router.http(Method::PUT, "/api/inputs/1/label", Some(json!({ "label": "Wide" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "label": "Wide" }));
+    assert_eq!(panel.read_block().await, "INPUT LABELS:\n1 Wide");
+
+    let (status, _) = router.http(Method::PUT, "/api/outputs/0/label", Some(json!({ "label": "PGM" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(panel.read_block().await, "OUTPUT LABELS:\n0 PGM");
+
+    let (status, _) = router.http(Method::PUT, "/api/outputs/0/label", Some(json!({ "label": "two\nlines" }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = router.http(Method::PUT, "/api/inputs/9/label", Some(json!({ "label": "Nope" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    panel.assert_nothing_sent().await;
+
+    let (_, output) = router.http(Method::GET, "/api/outputs/0", None).await;
+    assert_eq!(output["label"], "PGM");
+}
+
+#[tokio::test]
+async fn locks_are_shared_with_panels() {
+    let router = Router::start("http-locks", &["CAM 1", "CAM 2"]).await;
+    let mut panel = Client::connect(&router).await;
+
+    let (status, body) = router.http(Method::PUT, "/api/outputs/1/lock", Some(json!({ "lock": "owned" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "lock": "owned" }));
+    assert_eq!(panel.read_block().await, "VIDEO OUTPUT LOCKS:\n1 L");
+
+    panel.send("VIDEO OUTPUT ROUTING:\n1 0\n").await;
+    assert_eq!(panel.read_block().await, "NAK");
+
+    panel.send("VIDEO OUTPUT LOCKS:\n2 O\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    let (status, _) = router.http(Method::PUT, "/api/outputs/2/lock", Some(json!({ "lock": "unlocked" }))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, output) = router.http(Method::GET, "/api/outputs/2", None).await;
+    assert_eq!(output["lock"], "locked");
+
+    let (status, _) = router.http(Method::PUT, "/api/outputs/2/lock", Some(json!({ "lock": "force" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(panel.read_block().await, "VIDEO OUTPUT LOCKS:\n2 U");
+}
+
+#[tokio::test]
+async fn event_stream_starts_with_a_snapshot() {
+    let router = Router::start("events-snapshot", &["CAM 1", "CAM 2"]).await;
+    let panel = Client::connect(&router).await;
+    let panel_addr = panel.lines.get_ref().local_addr().unwrap();
+
+    let (_, snapshot) = router.events().await;
+    let (_, inputs) = router.http(Method::GET, "/api/inputs", None).await;
+    let (_, outputs) = router.http(Method::GET, "/api/outputs", None).await;
+
+    assert_eq!(snapshot["inputs"], inputs);
+    assert_eq!(snapshot["outputs"], outputs);
+    assert_eq!(snapshot["controllers"], json!([panel_addr.to_string()]));
+}
+
+#[tokio::test]
+async fn event_stream_follows_changes_from_panels_and_http() {
+    let router = Router::start("events-changes", &["CAM 1", "CAM 2"]).await;
+    let mut panel = Client::connect(&router).await;
+    let (mut events, _) = router.events().await;
+
+    panel.send("VIDEO OUTPUT ROUTING:\n2 1\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    assert_eq!(events.next().await, json!({ "type": "routes", "routes": [{ "output": 2, "input": 1 }] }));
+
+    panel.send("OUTPUT LABELS:\n2 PGM\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    assert_eq!(events.next().await, json!({ "type": "output_labels", "labels": [{ "index": 2, "label": "PGM" }] }));
+
+    panel.send("VIDEO OUTPUT LOCKS:\n2 O\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    assert_eq!(events.next().await, json!({ "type": "locks", "locks": [{ "output": 2, "lock": "locked" }] }));
+
+    router.http(Method::PUT, "/api/outputs/1/lock", Some(json!({ "lock": "owned" }))).await;
+    assert_eq!(events.next().await, json!({ "type": "locks", "locks": [{ "output": 1, "lock": "owned" }] }));
+
+    router.http(Method::PUT, "/api/inputs/0/label", Some(json!({ "label": "Wide" }))).await;
+    assert_eq!(events.next().await, json!({ "type": "input_labels", "labels": [{ "index": 0, "label": "Wide" }] }));
+
+    router.http(Method::POST, "/api/routes", Some(json!([{ "output": 0, "input": 0 }]))).await;
+    assert_eq!(events.next().await, json!({ "type": "routes", "routes": [{ "output": 0, "input": 0 }] }));
+}
+
+#[tokio::test]
+async fn event_stream_reports_sources_and_controllers() {
+    let router = Router::start("events-sources", &["CAM 1", "CAM 2"]).await;
+    let (mut events, _) = router.events().await;
+
+    let panel = Client::connect(&router).await;
+    let panel_addr = panel.lines.get_ref().local_addr().unwrap().to_string();
+    assert_eq!(events.next().await, json!({ "type": "controller_connected", "addr": panel_addr }));
+
+    drop(panel);
+    assert_eq!(events.next().await, json!({ "type": "controller_disconnected", "addr": panel_addr }));
+
+    router.backend.remove_source("CAM 2");
+    assert_eq!(events.next().await, json!({
+        "type": "source", "index": 1, "ndi_name": "CAM 2", "ip": "10.0.0.2:5961", "online": false,
+    }));
+
+    router.backend.add_source("CAM 3", "10.0.0.3:5961");
+    assert_eq!(events.next().await, json!({
+        "type": "source", "index": 2, "ndi_name": "CAM 3", "ip": "10.0.0.3:5961", "online": true,
+    }));
+    assert_eq!(events.next().await, json!({ "type": "input_labels", "labels": [{ "index": 2, "label": "CAM 3" }] }));
+}
+
+#[tokio::test]
+async fn serves_the_web_panel() {
+    let router = Router::start("panel", &["CAM 1"]).await;
+
+    for (path, content_type) in &[
+        ("/", "text/html"),
+        ("/index.html", "text/html"),
+        ("/panel.js", "application/javascript"),
+        ("/panel.css", "text/css"),
+    ] {
+        let (status, headers, body) = router.get(path).await;
+        assert_eq!(status, StatusCode::OK, "{}", path);
+        assert!(headers["content-type"].to_str().unwrap().starts_with(content_type), "{}", path);
+        assert!(!body.is_empty());
+    }
+
+    let (_, _, page) = router.get("/").await;
+    assert!(page.contains(r#"<script src="/panel.js">"#));
+
+    let (status, _, _) = router.get("/missing.js").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
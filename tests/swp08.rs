@@ -0,0 +1,256 @@
+//! Tests of the SW-P-08 codec, and end to end tests of Probel controllers
+//! routing alongside Videohub clients.
+
+mod common;
+
+use bytes::BytesMut;
+use quickcheck::{quickcheck, Arbitrary, Gen};
+use tokio_util::codec::{Decoder, Encoder};
+
+use ndi_router::swp08::{Frame, Message, NameLength, Swp08Codec, MAX_PORT};
+use ndi_router::config::Config;
+use ndi_router::swp08::server::{MAX_SOURCE, NO_SOURCE};
+
+use common::{Client, Probel, Router, NUM_OUTPUTS};
+
+fn port<G: Gen>(g: &mut G) -> usize {
+    usize::arbitrary(g) % (MAX_PORT + 1)
+}
+
+fn nibble<G: Gen>(g: &mut G) -> u8 {
+    u8::arbitrary(g) & 0x0F
+}
+
+fn name_length<G: Gen>(g: &mut G) -> NameLength {
+    [NameLength::Four, NameLength::Eight, NameLength::Twelve][usize::arbitrary(g) % 3]
+}
+
+fn names<G: Gen>(g: &mut G, length: NameLength) -> Vec<String> {
+    let count = usize::arbitrary(g) % (length.max_names() + 1);
+    (0..count).map(|_| length.fit(&String::arbitrary(g))).collect()
+}
+
+#[derive(Clone, Debug)]
+struct ArbitraryMessage(Message);
+
+impl Arbitrary for ArbitraryMessage {
+    fn arbitrary<G: Gen>(g: &mut G) -> ArbitraryMessage {
+        let (matrix, level) = (nibble(g), nibble(g));
+
+        let message = match u8::arbitrary(g) % 10 {
+            0 => Message::Interrogate { matrix, level, dest: port(g) },
+            1 => Message::Connect { matrix, level, dest: port(g), source: port(g) },
+            2 => Message::Tally { matrix, level, dest: port(g), source: port(g) },
+            3 => Message::Connected { matrix, level, dest: port(g), source: port(g) },
+            4 => Message::TallyDumpRequest { matrix, level },
+            5 => {
+                let count = usize::arbitrary(g) % 65;
+                Message::TallyDump {
+                    matrix,
+                    level,
+                    first_dest: u16::arbitrary(g) as usize,
+                    sources: (0..count).map(|_| u16::arbitrary(g) as usize).collect(),
+                }
+            },
+            6 => Message::SourceNamesRequest { matrix, level, length: name_length(g) },
+            7 => Message::DestNamesRequest { matrix, length: name_length(g) },
+            8 => {
+                let length = name_length(g);
+                let names = names(g, length);
+                Message::SourceNames { matrix, level, length, first: u16::arbitrary(g) as usize, names }
+            },
+            _ => {
+                let length = name_length(g);
+                let names = names(g, length);
+                Message::DestNames { matrix, length, first: u16::arbitrary(g) as usize, names }
+            },
+        };
+
+        ArbitraryMessage(message)
+    }
+}
+
+fn encode(frames: &[Frame]) -> BytesMut {
+    let mut codec = Swp08Codec::new();
+    let mut buf = BytesMut::new();
+
+    for frame in frames {
+        codec.encode(frame.clone(), &mut buf).unwrap();
+    }
+
+    buf
+}
+
+/// Decodes `bytes` fed to the codec `chunk` bytes at a time.
+fn decode(bytes: &[u8], chunk: usize) -> Vec<Frame> {
+    let mut codec = Swp08Codec::new();
+    let mut buf = BytesMut::new();
+    let mut frames = Vec::new();
+
+    for piece in bytes.chunks(chunk.max(1)) {
+        buf.extend_from_slice(piece);
+        while let Some(frame) = codec.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+    }
+
+    frames
+}
+
+quickcheck! {
+    fn messages_round_trip(messages: Vec<ArbitraryMessage>, chunk: usize) -> bool {
+        let frames: Vec<Frame> = messages.into_iter()
+            .map(|ArbitraryMessage(message)| Frame::Message(message))
+            .collect();
+
+        decode(&encode(&frames), chunk % 32) == frames
+    }
+}
+
+#[test]
+fn encodes_a_connect() {
+    let connect = Message::Connect { matrix: 0, level: 0, dest: 130, source: 5 };
+
+    // Destination 130 is 1 * 128 + 2, the byte count is 5 and the checksum
+    // makes the whole lot add up to 0.
+    assert_eq!(
+        &encode(&[Frame::Message(connect)])[..],
+        &[0x10, 0x02, 0x02, 0x00, 0x10, 0x10, 0x02, 0x05, 0x05, 0xE2, 0x10, 0x03][..],
+    );
+}
+
+#[test]
+fn decodes_acks_and_skips_noise() {
+    let bytes = [0xFF, 0x10, 0x06, 0x00, 0x10, 0x15, 0x10, 0x02, 0x01, 0x00, 0x00, 0x03, 0x04, 0xF8, 0x10, 0x03];
+
+    assert_eq!(decode(&bytes, bytes.len()), vec![
+        Frame::Ack,
+        Frame::Nak,
+        Frame::Message(Message::Interrogate { matrix: 0, level: 0, dest: 3 }),
+    ]);
+}
+
+#[test]
+fn damaged_messages_are_corrupt() {
+    // A bad checksum, then a byte count that does not match.
+    let bytes = [
+        0x10, 0x02, 0x01, 0x00, 0x00, 0x03, 0x04, 0xF9, 0x10, 0x03,
+        0x10, 0x02, 0x01, 0x00, 0x00, 0x03, 0x05, 0xF7, 0x10, 0x03,
+    ];
+
+    assert_eq!(decode(&bytes, 3), vec![Frame::Corrupt, Frame::Corrupt]);
+}
+
+#[test]
+fn names_are_padded_and_cut_to_length() {
+    assert_eq!(NameLength::Four.fit("CAM"), "CAM ");
+    assert_eq!(NameLength::Eight.fit("Camera 12 wide"), "Camera 1");
+    assert_eq!(NameLength::Four.fit("Café"), "Caf?");
+}
+
+#[tokio::test]
+async fn routes_made_by_probel_controllers_reach_everyone() {
+    let router = Router::start("swp08-connect", &["CAM 1", "CAM 2"]).await;
+    let mut probel = Probel::connect(&router).await;
+    let mut watcher = Probel::connect(&router).await;
+    let mut panel = Client::connect(&router).await;
+
+    probel.send(Message::Interrogate { matrix: 0, level: 0, dest: 2 }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::Tally { matrix: 0, level: 0, dest: 2, source: NO_SOURCE }).await;
+
+    probel.send(Message::Connect { matrix: 0, level: 0, dest: 2, source: 1 }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::Connected { matrix: 0, level: 0, dest: 2, source: 1 }).await;
+    watcher.expect(Message::Connected { matrix: 0, level: 0, dest: 2, source: 1 }).await;
+    assert_eq!(panel.read_block().await, "VIDEO OUTPUT ROUTING:\n2 1");
+    assert_eq!(router.backend.routed_source("NDI output 2"), Some("CAM 2".to_owned()));
+
+    // Other matrices and levels are not this router's to answer for.
+    probel.send(Message::Connect { matrix: 1, level: 0, dest: 0, source: 0 }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.assert_nothing_sent().await;
+    panel.assert_nothing_sent().await;
+}
+
+#[tokio::test]
+async fn probel_controllers_follow_videohub_routes_and_locks() {
+    let router = Router::start("swp08-follow", &["CAM 1", "CAM 2"]).await;
+    let mut probel = Probel::connect(&router).await;
+    let mut panel = Client::connect(&router).await;
+
+    panel.send("VIDEO OUTPUT ROUTING:\n0 1\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    probel.expect(Message::Connected { matrix: 0, level: 0, dest: 0, source: 1 }).await;
+
+    // A refused connect is answered with the route the destination keeps.
+    panel.send("VIDEO OUTPUT LOCKS:\n0 O\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    probel.send(Message::Connect { matrix: 0, level: 0, dest: 0, source: 0 }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::Tally { matrix: 0, level: 0, dest: 0, source: 1 }).await;
+    panel.assert_nothing_sent().await;
+
+    probel.send(Message::TallyDumpRequest { matrix: 0, level: 0 }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    let mut sources = vec![NO_SOURCE; NUM_OUTPUTS];
+    sources[0] = 1;
+    probel.expect(Message::TallyDump { matrix: 0, level: 0, first_dest: 0, sources }).await;
+}
+
+#[tokio::test]
+async fn names_come_from_labels() {
+    let router = Router::start("swp08-names", &["CAM 1", "Camera two"]).await;
+    let mut probel = Probel::connect(&router).await;
+    let mut panel = Client::connect(&router).await;
+
+    panel.send("OUTPUT LABELS:\n1 PGM\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+
+    probel.send(Message::SourceNamesRequest { matrix: 0, level: 0, length: NameLength::Eight }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::SourceNames {
+        matrix: 0,
+        level: 0,
+        length: NameLength::Eight,
+        first: 0,
+        names: vec!["CAM 1   ".to_owned(), "Camera t".to_owned()],
+    }).await;
+
+    probel.send(Message::DestNamesRequest { matrix: 0, length: NameLength::Four }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::DestNames {
+        matrix: 0,
+        length: NameLength::Four,
+        first: 0,
+        names: vec!["NDI ".to_owned(), "PGM ".to_owned(), "NDI ".to_owned(), "NDI ".to_owned()],
+    }).await;
+}
+
+#[tokio::test]
+async fn ports_beyond_swp08_are_not_reported_as_lower_ones() {
+    let names: Vec<String> = (0..=1030).map(|i| format!("CAM {}", i + 1)).collect();
+    let sources: Vec<&str> = names.iter().map(String::as_str).collect();
+    let router = Router::start_with("swp08-range", &sources, Config { outputs: 1031, ..Config::default() }).await;
+    let mut probel = Probel::connect(&router).await;
+    let mut panel = Client::connect(&router).await;
+
+    // Output 1030 and input 1030 would alias 6 if they were sent.
+    panel.send("VIDEO OUTPUT ROUTING:\n1030 1\n1 1030\n").await;
+    assert_eq!(panel.read_block().await, "ACK");
+    probel.assert_nothing_sent().await;
+
+    probel.send(Message::Interrogate { matrix: 0, level: 0, dest: 1 }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::Tally { matrix: 0, level: 0, dest: 1, source: NO_SOURCE }).await;
+
+    // The source meaning no source cannot be routed.
+    probel.send(Message::Connect { matrix: 0, level: 0, dest: 2, source: NO_SOURCE }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::Tally { matrix: 0, level: 0, dest: 2, source: NO_SOURCE }).await;
+    panel.assert_nothing_sent().await;
+
+    probel.send(Message::Connect { matrix: 0, level: 0, dest: 2, source: MAX_SOURCE }).await;
+    assert_eq!(probel.read().await, Frame::Ack);
+    probel.expect(Message::Connected { matrix: 0, level: 0, dest: 2, source: MAX_SOURCE }).await;
+}
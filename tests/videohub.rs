@@ -0,0 +1,616 @@
+//! End to end tests of the Videohub server, driving it over TCP the way a
+//! Videohub Control panel would, with the NDI network simulated.
+
+mod common;
+
+use tokio::time;
+use serde_json::{json, Value};
+use std::{env, fs, process};
+use std::time::Duration;
+
+use ndi_router::config::{Config};
+use ndi_router::salvo::{Port, SalvoRoute};
+use ndi_router::ndi::{RouteCall, SimulatedBackend};
+
+use common::{unique_id, Client, Router, NUM_OUTPUTS};
+
+#[tokio::test]
+async fn sends_initial_status_dump() {
+    let router = Router::start("dump", &["CAM 1", "CAM 2"]).await;
+    let client = Client::connect(&router).await;
+
+    let unique_id = unique_id(&client.dump[1]);
+    assert_eq!(unique_id.len(), 12);
+    assert!(unique_id.chars().all(|c| c.is_ascii_hexdigit()));
+
+    assert_eq!(client.dump.len(), 8);
+    assert_eq!(client.dump[0], "PROTOCOL PREAMBLE:\nVersion: 2.8");
+    assert_eq!(client.dump[1], format!("VIDEOHUB DEVICE:\n\
+        Device present: true\n\
+        Model name: Blackmagic Smart Videohub\n\
+        Friendly name: NDI Router\n\
+        Unique ID: {}\n\
+        Video inputs: 2\n\
+        Video processing units: 0\n\
+        Video outputs: 4\n\
+        Video monitoring outputs: 0\n\
+        Serial ports: 0", unique_id));
+    assert_eq!(client.dump[2], "INPUT LABELS:\n0 CAM 1\n1 CAM 2");
+    assert_eq!(client.dump[3], "OUTPUT LABELS:\n0 NDI Output 0\n1 NDI Output 1\n2 NDI Output 2\n3 NDI Output 3");
+    assert_eq!(client.dump[4], "VIDEO OUTPUT ROUTING:");
+    assert_eq!(client.dump[5], "VIDEO OUTPUT LOCKS:\n0 U\n1 U\n2 U\n3 U");
+    assert_eq!(client.dump[6], "CONFIGURATION:\nTake Mode: false");
+    assert_eq!(client.dump[7], "END PRELUDE:");
+}
+
+#[tokio::test]
+async fn routes_and_broadcasts_to_other_clients() {
+    let router = Router::start("route", &["CAM 1", "CAM 2", "CAM 3"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n1 2\n").await;
+
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n1 2");
+    assert_eq!(router.backend.take_route_calls(), vec![
+        RouteCall::Clear { output: "NDI output 1".to_owned() },
+        RouteCall::Change { output: "NDI output 1".to_owned(), source: "CAM 3".to_owned() },
+    ]);
+    a.assert_nothing_sent().await;
+
+    // A panel joining later sees what is on air.
+    let c = Client::connect(&router).await;
+    assert_eq!(c.dump[4], "VIDEO OUTPUT ROUTING:\n1 2");
+}
+
+#[tokio::test]
+async fn applies_every_line_of_a_block() {
+    let router = Router::start("multi", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n3 1\n0 1\n2 0\n").await;
+
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n0 1\n2 0\n3 1");
+    assert_eq!(router.backend.routed_source("NDI output 0"), Some("CAM 2".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 2"), Some("CAM 1".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 3"), Some("CAM 2".to_owned()));
+}
+
+#[tokio::test]
+async fn routes_outputs_beyond_255() {
+    let router = Router::start_with("large", &["CAM 1", "CAM 2"], Config { outputs: 300, ..Config::default() }).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n299 1\n256 0\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n256 0\n299 1");
+    assert_eq!(router.backend.routed_source("NDI output 256"), Some("CAM 1".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 299"), Some("CAM 2".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 0"), None);
+
+    let c = Client::connect(&router).await;
+    assert!(c.dump[1].contains("\nVideo outputs: 300\n"));
+    assert_eq!(c.dump[4], "VIDEO OUTPUT ROUTING:\n256 0\n299 1");
+}
+
+#[tokio::test]
+async fn naks_invalid_blocks_without_changing_anything() {
+    let router = Router::start("nak", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    let invalid = [
+        "VIDEO OUTPUT ROUTING:\n0 1\n9 0\n",
+        "VIDEO OUTPUT ROUTING:\n0 5\n",
+        "VIDEO OUTPUT ROUTING:\n0\n",
+        "VIDEO OUTPUT ROUTING:\nzero one\n",
+        "VIDEO OUTPUT ROUTING:\n-1 0\n",
+        "VIDEO OUTPUT LOCKS:\n0 X\n",
+        "OUTPUT LABELS:\n7 Nowhere\n",
+        "NOT A REAL BLOCK:\n0 1\n",
+    ];
+
+    for block in invalid.iter() {
+        a.send(block).await;
+        assert_eq!(a.read_block().await, "NAK", "{:?} was accepted", block);
+    }
+
+    assert_eq!(router.backend.take_route_calls(), vec![]);
+    b.assert_nothing_sent().await;
+}
+
+#[tokio::test]
+async fn answers_status_requests_with_the_current_block() {
+    let router = Router::start("request", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n2 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    a.send("VIDEO OUTPUT LOCKS:\n0 O\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    b.read_block().await;
+    b.read_block().await;
+
+    let requests = [
+        ("PROTOCOL PREAMBLE:\n", "PROTOCOL PREAMBLE:\nVersion: 2.8".to_owned()),
+        ("VIDEOHUB DEVICE:\n", b.dump[1].clone()),
+        ("INPUT LABELS:\n", "INPUT LABELS:\n0 CAM 1\n1 CAM 2".to_owned()),
+        ("OUTPUT LABELS:\n", b.dump[3].clone()),
+        ("VIDEO OUTPUT ROUTING:\n", "VIDEO OUTPUT ROUTING:\n2 1".to_owned()),
+        ("VIDEO OUTPUT LOCKS:\n", "VIDEO OUTPUT LOCKS:\n0 L\n1 U\n2 U\n3 U".to_owned()),
+        ("CONFIGURATION:\n", "CONFIGURATION:\nTake Mode: false".to_owned()),
+        ("TAKE MODE:\n", "TAKE MODE:\n0 false\n1 false\n2 false\n3 false".to_owned()),
+        ("PENDING VIDEO OUTPUT ROUTING:\n", "PENDING VIDEO OUTPUT ROUTING:".to_owned()),
+    ];
+
+    for (request, expected) in requests.iter() {
+        b.send(request).await;
+        assert_eq!(b.read_block().await, "ACK");
+        assert_eq!(b.read_block().await, *expected);
+    }
+
+    a.assert_nothing_sent().await;
+}
+
+#[tokio::test]
+async fn locks_are_owned_by_one_client() {
+    let router = Router::start("locks", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT LOCKS:\n0 O\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT LOCKS:\n0 L");
+
+    // A new panel sees the lock as someone else's.
+    let c = Client::connect(&router).await;
+    assert_eq!(c.dump[5], "VIDEO OUTPUT LOCKS:\n0 L\n1 U\n2 U\n3 U");
+
+    b.send("VIDEO OUTPUT ROUTING:\n0 1\n").await;
+    assert_eq!(b.read_block().await, "NAK");
+    b.send("VIDEO OUTPUT LOCKS:\n0 U\n").await;
+    assert_eq!(b.read_block().await, "NAK");
+    assert_eq!(router.backend.take_route_calls(), vec![]);
+
+    // The owner can still route the output.
+    a.send("VIDEO OUTPUT ROUTING:\n0 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n0 1");
+}
+
+#[tokio::test]
+async fn locks_are_released_on_disconnect() {
+    let router = Router::start("release", &["CAM 1"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT LOCKS:\n2 O\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT LOCKS:\n2 L");
+
+    drop(a);
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT LOCKS:\n2 U");
+}
+
+#[tokio::test]
+async fn locks_can_be_forced_off() {
+    let router = Router::start("force", &["CAM 1"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT LOCKS:\n1 O\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT LOCKS:\n1 L");
+
+    b.send("VIDEO OUTPUT LOCKS:\n1 F\n").await;
+    assert_eq!(b.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "VIDEO OUTPUT LOCKS:\n1 U");
+}
+
+#[tokio::test]
+async fn labels_are_changed_and_broadcast() {
+    let router = Router::start("labels", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("OUTPUT LABELS:\n0 Studio A MV 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "OUTPUT LABELS:\n0 Studio A MV 1");
+
+    a.send("INPUT LABELS:\n1 Wide shot\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "INPUT LABELS:\n1 Wide shot");
+
+    let c = Client::connect(&router).await;
+    assert_eq!(c.dump[2], "INPUT LABELS:\n0 CAM 1\n1 Wide shot");
+    assert_eq!(c.dump[3], "OUTPUT LABELS:\n0 Studio A MV 1\n1 NDI Output 1\n2 NDI Output 2\n3 NDI Output 3");
+}
+
+#[tokio::test]
+async fn new_sources_are_pushed_to_clients() {
+    let router = Router::start("discovery", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+
+    router.backend.add_source("CAM 3", "10.0.0.3:5961");
+
+    assert!(a.read_block().await.contains("Video inputs: 3"));
+    assert_eq!(a.read_block().await, "INPUT LABELS:\n2 CAM 3");
+
+    // A source going away keeps its input number.
+    router.backend.remove_source("CAM 1");
+    router.backend.add_source("CAM 4", "10.0.0.4:5961");
+
+    assert!(a.read_block().await.contains("Video inputs: 4"));
+    assert_eq!(a.read_block().await, "INPUT LABELS:\n3 CAM 4");
+}
+
+#[tokio::test]
+async fn sources_are_only_numbered_once_the_source_map_is_saved() {
+    let dir = env::temp_dir().join(format!("ndi-router-test-{}-unsaved-sources", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    // A directory in the way of the temporary file makes saving the map fail.
+    fs::create_dir_all(dir.join("sources.json.tmp")).unwrap();
+
+    let backend = SimulatedBackend::new();
+    backend.add_source("CAM 1", "10.0.0.1:5961");
+    let router = Router::start_in(dir.clone(), backend, 0, Config { outputs: NUM_OUTPUTS, ..Config::default() }).await;
+
+    time::delay_for(Duration::from_millis(1500)).await;
+    {
+        let state = router.state.lock().await;
+        assert_eq!(state.inputs.len(), 0);
+        assert_eq!(state.source_map.slot("CAM 1"), None);
+    }
+
+    // The source is numbered when the sources next change and the map can
+    // be saved.
+    fs::remove_dir(dir.join("sources.json.tmp")).unwrap();
+    router.backend.add_source("CAM 2", "10.0.0.2:5961");
+    router.wait_for_inputs(2).await;
+
+    let saved: Value = serde_json::from_slice(&fs::read(dir.join("sources.json")).unwrap()).unwrap();
+    assert_eq!(saved, json!(["CAM 1", "CAM 2"]));
+}
+
+#[tokio::test]
+async fn source_names_with_a_nul_are_dropped_from_the_source_map() {
+    let dir = env::temp_dir().join(format!("ndi-router-test-{}-nul-sources", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("sources.json"), r#"["CAM 1", "CAM\u00002", "CAM 3"]"#).unwrap();
+
+    let backend = SimulatedBackend::new();
+    backend.add_source("CAM 1", "10.0.0.1:5961");
+    backend.add_source("CAM 3", "10.0.0.3:5961");
+    let router = Router::start_in(dir, backend, 2, Config { outputs: NUM_OUTPUTS, ..Config::default() }).await;
+
+    assert_eq!(router.state.lock().await.source_map.slots(), &[Some("CAM 1".to_owned()), None, Some("CAM 3".to_owned())]);
+}
+
+#[tokio::test]
+async fn state_survives_a_restart() {
+    let router = Router::start("restart", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n3 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    a.send("OUTPUT LABELS:\n3 Studio B PGM\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    router.backend.take_route_calls();
+
+    let router = router.restart().await;
+    let b = Client::connect(&router).await;
+
+    assert_eq!(b.dump[2], "INPUT LABELS:\n0 CAM 1\n1 CAM 2");
+    assert_eq!(b.dump[3], "OUTPUT LABELS:\n0 NDI Output 0\n1 NDI Output 1\n2 NDI Output 2\n3 Studio B PGM");
+    assert_eq!(b.dump[4], "VIDEO OUTPUT ROUTING:\n3 1");
+    assert_eq!(router.backend.routed_source("NDI output 3"), Some("CAM 2".to_owned()));
+}
+
+#[tokio::test]
+async fn locks_survive_a_restart_without_an_owner() {
+    let router = Router::start("restart-locks", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT LOCKS:\n1 O\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+
+    // Only which outputs are locked is saved, not who locked them.
+    router.flush_state().await;
+    let saved: Value = serde_json::from_slice(&fs::read(router.dir.join("state.json")).unwrap()).unwrap();
+    assert_eq!(saved["locks"], json!([1]));
+
+    let router = router.restart().await;
+    let mut b = Client::connect(&router).await;
+    assert_eq!(b.dump[5], "VIDEO OUTPUT LOCKS:\n0 U\n1 L\n2 U\n3 U");
+
+    b.send("VIDEO OUTPUT LOCKS:\n1 U\n").await;
+    assert_eq!(b.read_block().await, "NAK");
+    b.send("VIDEO OUTPUT LOCKS:\n1 O\n").await;
+    assert_eq!(b.read_block().await, "NAK");
+    b.send("VIDEO OUTPUT ROUTING:\n1 0\n").await;
+    assert_eq!(b.read_block().await, "NAK");
+
+    b.send("VIDEO OUTPUT LOCKS:\n1 F\n").await;
+    assert_eq!(b.read_block().await, "ACK");
+    b.send("VIDEO OUTPUT LOCKS:\n1 O\n").await;
+    assert_eq!(b.read_block().await, "ACK");
+
+    let c = Client::connect(&router).await;
+    assert_eq!(c.dump[5], "VIDEO OUTPUT LOCKS:\n0 U\n1 L\n2 U\n3 U");
+}
+
+#[tokio::test]
+async fn friendly_name_is_changed_and_identity_survives_a_restart() {
+    let router = Router::start("identity", &["CAM 1"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+    let id = unique_id(&a.dump[1]);
+
+    a.send("CONFIGURATION:\nTake Mode: maybe\n").await;
+    assert_eq!(a.read_block().await, "NAK");
+
+    a.send("CONFIGURATION:\nFriendly name: Studio A Router\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+
+    let device = b.read_block().await;
+    assert!(device.contains("\nFriendly name: Studio A Router\n"), "{}", device);
+    assert_eq!(unique_id(&device), id);
+    a.assert_nothing_sent().await;
+
+    let router = router.restart().await;
+    let c = Client::connect(&router).await;
+
+    assert!(c.dump[1].contains("\nFriendly name: Studio A Router\n"), "{}", c.dump[1]);
+    assert_eq!(unique_id(&c.dump[1]), id);
+}
+
+/// A router with take mode on for outputs 0 and 1.
+async fn take_mode_router(name: &str, take_timeout: u64) -> Router {
+    let config = Config {
+        outputs: NUM_OUTPUTS,
+        take_mode: true,
+        take_outputs: vec![0, 1],
+        take_timeout,
+        ..Config::default()
+    };
+
+    Router::start_with(name, &["CAM 1", "CAM 2"], config).await
+}
+
+#[tokio::test]
+async fn routes_on_take_outputs_wait_for_a_take() {
+    let router = take_mode_router("take", 0).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+    assert_eq!(a.dump[6], "CONFIGURATION:\nTake Mode: true");
+    assert_eq!(a.dump[7], "TAKE MODE:\n0 true\n1 true\n2 false\n3 false");
+    assert_eq!(a.dump[8], "END PRELUDE:");
+
+    // Output 2 is not in take mode so is routed straight away.
+    a.send("VIDEO OUTPUT ROUTING:\n0 1\n1 0\n2 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "PENDING VIDEO OUTPUT ROUTING:\n0 1\n1 0");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n2 1");
+    assert_eq!(b.read_block().await, "PENDING VIDEO OUTPUT ROUTING:\n0 1\n1 0");
+    assert_eq!(router.backend.routed_source("NDI output 0"), None);
+    assert_eq!(router.backend.routed_source("NDI output 2"), Some("CAM 2".to_owned()));
+
+    // A panel joining now is told what is pending.
+    let c = Client::connect(&router).await;
+    assert_eq!(c.dump[8], "PENDING VIDEO OUTPUT ROUTING:\n0 1\n1 0");
+    assert_eq!(c.dump[9], "END PRELUDE:");
+
+    b.send("TAKE:\n3\n").await;
+    assert_eq!(b.read_block().await, "NAK");
+
+    b.send("TAKE:\n1\n").await;
+    assert_eq!(b.read_block().await, "ACK");
+    for client in [&mut a, &mut b].iter_mut() {
+        assert_eq!(client.read_block().await, "VIDEO OUTPUT ROUTING:\n1 0");
+        assert_eq!(client.read_block().await, "PENDING VIDEO OUTPUT ROUTING:\n0 1");
+    }
+    assert_eq!(router.backend.routed_source("NDI output 1"), Some("CAM 1".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 0"), None);
+
+    a.send("TAKE:\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "VIDEO OUTPUT ROUTING:\n0 1");
+    assert_eq!(a.read_block().await, "PENDING VIDEO OUTPUT ROUTING:");
+    assert_eq!(router.backend.routed_source("NDI output 0"), Some("CAM 2".to_owned()));
+}
+
+#[tokio::test]
+async fn turning_take_mode_off_drops_pending_routes() {
+    let router = take_mode_router("take-off", 0).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n0 1\n1 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    a.read_block().await;
+    b.read_block().await;
+
+    a.send("TAKE MODE:\n0 false\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "PENDING VIDEO OUTPUT ROUTING:\n1 1");
+    assert_eq!(b.read_block().await, "TAKE MODE:\n0 false");
+    assert_eq!(b.read_block().await, "PENDING VIDEO OUTPUT ROUTING:\n1 1");
+
+    // With output 0 out of take mode, routes to it apply straight away.
+    a.send("VIDEO OUTPUT ROUTING:\n0 0\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n0 0");
+
+    a.send("CONFIGURATION:\nTake Mode: false\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "PENDING VIDEO OUTPUT ROUTING:");
+    assert_eq!(b.read_block().await, "CONFIGURATION:\nTake Mode: false");
+    assert_eq!(b.read_block().await, "PENDING VIDEO OUTPUT ROUTING:");
+    assert_eq!(router.backend.routed_source("NDI output 1"), None);
+}
+
+#[tokio::test]
+async fn pending_routes_that_are_not_taken_expire() {
+    let router = take_mode_router("take-timeout", 1).await;
+    let mut a = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n0 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "PENDING VIDEO OUTPUT ROUTING:\n0 1");
+
+    assert_eq!(a.read_block().await, "PENDING VIDEO OUTPUT ROUTING:");
+    a.send("TAKE:\n0\n").await;
+    assert_eq!(a.read_block().await, "NAK");
+    assert_eq!(router.backend.routed_source("NDI output 0"), None);
+}
+
+#[tokio::test]
+async fn salvos_switch_together_around_locked_outputs() {
+    let route = |output, input| SalvoRoute { output, input };
+    let mut config = Config { outputs: NUM_OUTPUTS, ..Config::default() };
+    config.salvos.insert("Rehearsal".to_owned(), vec![
+        route(Port::Index(0), Port::Name("CAM 1".to_owned())),
+        route(Port::Name("NDI output 1".to_owned()), Port::Index(1)),
+        route(Port::Index(2), Port::Name("CAM 9".to_owned())),
+        route(Port::Index(3), Port::Index(0)),
+    ]);
+    config.salvos.insert("Show".to_owned(), vec![route(Port::Index(0), Port::Index(1))]);
+
+    let router = Router::start_with("salvo", &["CAM 1", "CAM 2"], config).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("SALVOS:\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "SALVOS:\n0 Rehearsal\n1 Show");
+
+    b.send("VIDEO OUTPUT LOCKS:\n3 O\n").await;
+    assert_eq!(b.read_block().await, "ACK");
+    a.read_block().await;
+    router.backend.take_route_calls();
+
+    a.send("FIRE SALVO:\nNope\n").await;
+    assert_eq!(a.read_block().await, "NAK");
+
+    a.send("FIRE SALVO:\nRehearsal\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "ROUTES REFUSED:\n2 no input 'CAM 9'\n3 locked");
+    assert_eq!(a.read_block().await, "VIDEO OUTPUT ROUTING:\n0 0\n1 1");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n0 0\n1 1");
+    b.assert_nothing_sent().await;
+
+    assert_eq!(router.backend.routed_source("NDI output 0"), Some("CAM 1".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 1"), Some("CAM 2".to_owned()));
+    assert_eq!(router.backend.routed_source("NDI output 2"), None);
+    assert_eq!(router.backend.routed_source("NDI output 3"), None);
+}
+
+#[tokio::test]
+async fn snapshots_recall_only_changed_routes() {
+    let router = Router::start("snapshot", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+    let mut b = Client::connect(&router).await;
+
+    a.send("VIDEO OUTPUT ROUTING:\n0 0\n1 1\n2 1\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    b.read_block().await;
+
+    a.send("SAVE SNAPSHOT:\nPre-show\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(b.read_block().await, "SNAPSHOTS:\n0 Pre-show");
+
+    a.send("VIDEO OUTPUT ROUTING:\n0 1\n2 0\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    b.read_block().await;
+    b.send("VIDEO OUTPUT LOCKS:\n2 O\n").await;
+    assert_eq!(b.read_block().await, "ACK");
+    a.read_block().await;
+    router.backend.take_route_calls();
+
+    a.send("RECALL SNAPSHOT:\nAfter-show\n").await;
+    assert_eq!(a.read_block().await, "NAK");
+
+    // Output 1 is unchanged so is left alone, output 2 is locked by b.
+    a.send("RECALL SNAPSHOT:\nPre-show\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "ROUTES REFUSED:\n2 locked");
+    assert_eq!(a.read_block().await, "VIDEO OUTPUT ROUTING:\n0 0");
+    assert_eq!(b.read_block().await, "VIDEO OUTPUT ROUTING:\n0 0");
+    assert_eq!(router.backend.take_route_calls(), vec![
+        RouteCall::Clear { output: "NDI output 0".to_owned() },
+        RouteCall::Change { output: "NDI output 0".to_owned(), source: "CAM 1".to_owned() },
+    ]);
+
+    // Snapshots are kept on disk, so are still there after a restart.
+    let router = router.restart().await;
+    let mut c = Client::connect(&router).await;
+
+    c.send("SNAPSHOTS:\n").await;
+    assert_eq!(c.read_block().await, "ACK");
+    assert_eq!(c.read_block().await, "SNAPSHOTS:\n0 Pre-show");
+
+    c.send("DELETE SNAPSHOT:\nPre-show\n").await;
+    assert_eq!(c.read_block().await, "ACK");
+    c.send("DELETE SNAPSHOT:\nPre-show\n").await;
+    assert_eq!(c.read_block().await, "NAK");
+    c.send("SNAPSHOTS:\n").await;
+    assert_eq!(c.read_block().await, "ACK");
+    assert_eq!(c.read_block().await, "SNAPSHOTS:");
+}
+
+#[tokio::test]
+async fn snapshots_are_unchanged_when_they_cannot_be_saved() {
+    let router = Router::start("snapshot-unsaved", &["CAM 1", "CAM 2"]).await;
+    let mut a = Client::connect(&router).await;
+
+    a.send("SAVE SNAPSHOT:\nPre-show\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+
+    // A directory in the way of the temporary file makes saving fail.
+    let blocker = router.dir.join("snapshots.json.tmp");
+    fs::create_dir(&blocker).unwrap();
+
+    a.send("SAVE SNAPSHOT:\nAfter-show\n").await;
+    assert_eq!(a.read_block().await, "NAK");
+    a.send("DELETE SNAPSHOT:\nPre-show\n").await;
+    assert_eq!(a.read_block().await, "NAK");
+    a.send("SNAPSHOTS:\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+    assert_eq!(a.read_block().await, "SNAPSHOTS:\n0 Pre-show");
+
+    // The next save that works does not bring back the failed changes.
+    fs::remove_dir(&blocker).unwrap();
+    a.send("SAVE SNAPSHOT:\nBreak\n").await;
+    assert_eq!(a.read_block().await, "ACK");
+
+    let saved: Value = serde_json::from_slice(&fs::read(router.dir.join("snapshots.json")).unwrap()).unwrap();
+    assert_eq!(saved.as_object().unwrap().keys().collect::<Vec<_>>(), vec!["Break", "Pre-show"]);
+}
+
+#[tokio::test]
+async fn ndi_groups_and_extra_ips_reach_the_backend() {
+    let config = Config {
+        outputs: 2,
+        output_names: vec![(1, "PGM".to_owned())].into_iter().collect(),
+        find_groups: vec!["Studio A".to_owned(), "Studio B".to_owned()],
+        extra_ips: vec!["10.0.0.5".to_owned(), "10.1.0.5".to_owned()],
+        output_groups: vec!["Router".to_owned()],
+        ..Config::default()
+    };
+    let router = Router::start_with("groups", &["CAM 1"], config).await;
+
+    assert_eq!(router.backend.finds(), vec![
+        (Some("Studio A,Studio B".to_owned()), Some("10.0.0.5,10.1.0.5".to_owned())),
+    ]);
+    assert_eq!(router.backend.outputs(), vec![
+        ("NDI output 0".to_owned(), Some("Router".to_owned())),
+        ("PGM".to_owned(), Some("Router".to_owned())),
+    ]);
+}